@@ -0,0 +1,142 @@
+//! The address alias book, an address argument can be given as `@NAME`.
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+use ckb_sdk::Address;
+use clap::Subcommand;
+
+/// The arguments accept an `@NAME` alias
+const ADDRESS_ARGS: &[&str] = &["--address", "--from-address", "--to-address"];
+
+#[derive(Subcommand, Debug)]
+pub enum AliasCommands {
+    /// Add (or replace) an address alias
+    Add {
+        /// The alias name (used as `@NAME`)
+        name: String,
+        address: Address,
+    },
+    /// Remove an address alias
+    Remove { name: String },
+    /// List all the address aliases
+    List,
+}
+
+pub fn invoke(cmd: AliasCommands) -> Result<(), Error> {
+    let mut aliases = load_aliases()?;
+    match cmd {
+        AliasCommands::Add { name, address } => {
+            if name.is_empty() || name.starts_with('@') || name.contains(char::is_whitespace) {
+                return Err(anyhow!("invalid alias name: {:?}", name));
+            }
+            aliases.insert(name, address.to_string());
+            save_aliases(&aliases)?;
+        }
+        AliasCommands::Remove { name } => {
+            if aliases.remove(&name).is_none() {
+                return Err(anyhow!("alias not found: {}", name));
+            }
+            save_aliases(&aliases)?;
+        }
+        AliasCommands::List => {
+            for (name, address) in &aliases {
+                println!("@{}: {}", name, address);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replace the `@NAME` values of the address arguments with the addresses in
+/// the alias book (both `--to-address @NAME` and `--to-address=@NAME`), the
+/// arguments which are not valid UTF-8 are kept as is.
+pub fn resolve_args(args: Vec<OsString>) -> Result<Vec<OsString>, Error> {
+    // The alias book is only loaded when an address argument uses an alias
+    let mut aliases: Option<BTreeMap<String, String>> = None;
+    let mut lookup = |value: &str| -> Result<String, Error> {
+        let name = match value.strip_prefix('@') {
+            Some(name) => name,
+            None => return Ok(value.to_string()),
+        };
+        if aliases.is_none() {
+            aliases = Some(load_aliases()?);
+        }
+        aliases
+            .as_ref()
+            .and_then(|aliases| aliases.get(name))
+            .cloned()
+            .ok_or_else(|| anyhow!("address alias not found: @{}", name))
+    };
+    let mut resolved = Vec::with_capacity(args.len());
+    let mut is_address_value = false;
+    for arg in args {
+        let value = match arg.to_str() {
+            Some(value) => value,
+            None => {
+                is_address_value = false;
+                resolved.push(arg);
+                continue;
+            }
+        };
+        if is_address_value {
+            is_address_value = false;
+            resolved.push(lookup(value)?.into());
+            continue;
+        }
+        match value.split_once('=') {
+            Some((flag, value)) if ADDRESS_ARGS.contains(&flag) => {
+                resolved.push(format!("{}={}", flag, lookup(value)?).into());
+            }
+            _ => {
+                is_address_value = ADDRESS_ARGS.contains(&value);
+                resolved.push(arg);
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/// `$CKB_CLI_LIGHT_CLIENT_HOME/aliases.toml`, default home is `~/.ckb-cli-light-client`
+fn aliases_path() -> Result<PathBuf, Error> {
+    let mut dir = if let Ok(dir) = env::var("CKB_CLI_LIGHT_CLIENT_HOME") {
+        PathBuf::from(dir)
+    } else if let Some(mut dir) = home::home_dir() {
+        dir.push(".ckb-cli-light-client");
+        dir
+    } else {
+        return Err(anyhow!(
+            "CKB_CLI_LIGHT_CLIENT_HOME environment variable not set, and can't get HOME directory"
+        ));
+    };
+    dir.push("aliases.toml");
+    Ok(dir)
+}
+
+fn load_aliases() -> Result<BTreeMap<String, String>, Error> {
+    let path = aliases_path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::default());
+    }
+    let content = fs::read_to_string(&path)?;
+    let aliases: BTreeMap<String, String> = toml::from_str(&content)
+        .map_err(|err| anyhow!("parse alias book {} error: {}", path.display(), err))?;
+    for (name, address) in &aliases {
+        Address::from_str(address)
+            .map_err(|err| anyhow!("invalid address of alias @{}: {}", name, err))?;
+    }
+    Ok(aliases)
+}
+
+fn save_aliases(aliases: &BTreeMap<String, String>) -> Result<(), Error> {
+    let path = aliases_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, toml::to_string(aliases)?)?;
+    Ok(())
+}
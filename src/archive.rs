@@ -0,0 +1,308 @@
+//! Read the files of a `.zip`, `.tar` or `.tar.gz` archive (only the regular
+//! files, the stored/deflated entries of zip).
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{anyhow, Error};
+use byteorder::{ByteOrder, LittleEndian};
+use flate2::read::{DeflateDecoder, GzDecoder};
+
+/// The max decompressed size of the archive (all the entries in total)
+const MAX_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The (file name, content) of the files in the archive, in file name order.
+/// The hidden files (and the `__MACOSX` metadata of the Finder made zip) are
+/// skipped.
+pub fn read_files(path: &Path) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    let data = fs::read(path)?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mut files = if name.ends_with(".zip") {
+        read_zip(&data)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        decompress(GzDecoder::new(data.as_slice()), MAX_DECOMPRESSED_SIZE)
+            .and_then(|tar_data| read_tar(&tar_data))
+    } else if name.ends_with(".tar") {
+        read_tar(&data)
+    } else {
+        Err(anyhow!(
+            "unsupported archive (.zip, .tar or .tar.gz expected)"
+        ))
+    }
+    .map_err(|err| anyhow!("read archive {} error: {}", path.display(), err))?;
+    files.retain(|(name, _)| !is_hidden(name));
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
+
+fn is_hidden(name: &str) -> bool {
+    name.split('/')
+        .any(|part| (part.starts_with('.') && part != "." && part != "..") || part == "__MACOSX")
+}
+
+/// Read all the decompressed data, at most <limit> bytes (the remaining of
+/// `MAX_DECOMPRESSED_SIZE`)
+fn decompress<R: Read>(reader: R, limit: u64) -> Result<Vec<u8>, Error> {
+    let mut content = Vec::new();
+    reader
+        .take(limit + 1)
+        .read_to_end(&mut content)
+        .map_err(|err| anyhow!("decompress error: {}", err))?;
+    if content.len() as u64 > limit {
+        return Err(anyhow!(
+            "the decompressed size exceeds {} bytes",
+            MAX_DECOMPRESSED_SIZE
+        ));
+    }
+    Ok(content)
+}
+
+fn read_tar(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    const BLOCK_SIZE: usize = 512;
+    let mut files = Vec::new();
+    let mut offset = 0;
+    while offset + BLOCK_SIZE <= data.len() {
+        let header = &data[offset..offset + BLOCK_SIZE];
+        // The archive ends with zero blocks
+        if header.iter().all(|b| *b == 0) {
+            break;
+        }
+        let field = |start: usize, len: usize| {
+            let value = &header[start..start + len];
+            let end = value.iter().position(|b| *b == 0).unwrap_or(len);
+            String::from_utf8_lossy(&value[..end]).trim().to_string()
+        };
+        let size_field = field(124, 12);
+        let size = usize::from_str_radix(&size_field, 8)
+            .map_err(|_| anyhow!("invalid tar entry size: {:?}", size_field))?;
+        // The ustar prefix of the long names
+        let name = match field(345, 155) {
+            prefix if !prefix.is_empty() && &header[257..262] == b"ustar" => {
+                format!("{}/{}", prefix, field(0, 100))
+            }
+            _ => field(0, 100),
+        };
+        let content_start = offset + BLOCK_SIZE;
+        let content_end = content_start + size;
+        if content_end > data.len() {
+            return Err(anyhow!("truncated tar entry: {}", name));
+        }
+        // '0' or '\0' is the regular file
+        if matches!(header[156], b'0' | 0) {
+            files.push((name, data[content_start..content_end].to_vec()));
+        }
+        offset = content_start + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+    }
+    Ok(files)
+}
+
+/// The signatures of the zip records
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const CENTRAL_SIGNATURE: u32 = 0x0201_4b50;
+const LOCAL_SIGNATURE: u32 = 0x0403_4b50;
+
+fn read_zip(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    let slice = |start: usize, len: usize| {
+        data.get(start..start + len)
+            .ok_or_else(|| anyhow!("truncated zip archive"))
+    };
+    // The end of central directory record is at the end (followed by the comment)
+    let eocd = (0..data.len().saturating_sub(21))
+        .rev()
+        .find(|pos| LittleEndian::read_u32(&data[*pos..]) == EOCD_SIGNATURE)
+        .ok_or_else(|| anyhow!("not a zip archive"))?;
+    let entries = LittleEndian::read_u16(slice(eocd + 10, 2)?) as usize;
+    let mut offset = LittleEndian::read_u32(slice(eocd + 16, 4)?) as usize;
+    let mut files = Vec::new();
+    let mut total_size = 0;
+    for _ in 0..entries {
+        let header = slice(offset, 46)?;
+        if LittleEndian::read_u32(header) != CENTRAL_SIGNATURE {
+            return Err(anyhow!("invalid zip central directory"));
+        }
+        let method = LittleEndian::read_u16(&header[10..]);
+        let compressed_size = LittleEndian::read_u32(&header[20..]) as usize;
+        let name_len = LittleEndian::read_u16(&header[28..]) as usize;
+        let extra_len = LittleEndian::read_u16(&header[30..]) as usize;
+        let comment_len = LittleEndian::read_u16(&header[32..]) as usize;
+        let local_offset = LittleEndian::read_u32(&header[42..]) as usize;
+        let name = String::from_utf8_lossy(slice(offset + 46, name_len)?).to_string();
+        offset += 46 + name_len + extra_len + comment_len;
+        if name.ends_with('/') {
+            continue;
+        }
+        let local = slice(local_offset, 30)?;
+        if LittleEndian::read_u32(local) != LOCAL_SIGNATURE {
+            return Err(anyhow!("invalid zip local header: {}", name));
+        }
+        let content_start = local_offset
+            + 30
+            + LittleEndian::read_u16(&local[26..]) as usize
+            + LittleEndian::read_u16(&local[28..]) as usize;
+        let compressed = slice(content_start, compressed_size)?;
+        let content = match method {
+            0 => compressed.to_vec(),
+            8 => decompress(
+                DeflateDecoder::new(compressed),
+                MAX_DECOMPRESSED_SIZE - total_size,
+            )
+            .map_err(|err| anyhow!("zip entry {}: {}", name, err))?,
+            _ => {
+                return Err(anyhow!(
+                    "unsupported compression method {} of zip entry: {}",
+                    method,
+                    name
+                ))
+            }
+        };
+        total_size += content.len() as u64;
+        if total_size > MAX_DECOMPRESSED_SIZE {
+            return Err(anyhow!(
+                "the decompressed size exceeds {} bytes",
+                MAX_DECOMPRESSED_SIZE
+            ));
+        }
+        files.push((name, content));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::DeflateEncoder, Compression};
+    use std::io::Write;
+
+    fn tar_entry(name: &str, prefix: &str, type_flag: u8, content: &[u8]) -> Vec<u8> {
+        let mut header = vec![0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        let size = format!("{:011o}\0", content.len());
+        header[124..136].copy_from_slice(size.as_bytes());
+        header[156] = type_flag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        let mut entry = header;
+        entry.extend_from_slice(content);
+        entry.resize(entry.len().div_ceil(512) * 512, 0);
+        entry
+    }
+
+    fn tar(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = entries.concat();
+        data.extend_from_slice(&[0u8; 1024]);
+        data
+    }
+
+    /// The zip archive of the entries: (name, compression method, content)
+    fn zip(entries: &[(&str, u16, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut central = Vec::new();
+        for (name, method, content) in entries {
+            let compressed = if *method == 8 {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(content).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                content.to_vec()
+            };
+            let local_offset = data.len() as u32;
+            let mut local = vec![0u8; 30];
+            LittleEndian::write_u32(&mut local[0..], LOCAL_SIGNATURE);
+            LittleEndian::write_u16(&mut local[8..], *method);
+            LittleEndian::write_u32(&mut local[18..], compressed.len() as u32);
+            LittleEndian::write_u32(&mut local[22..], content.len() as u32);
+            LittleEndian::write_u16(&mut local[26..], name.len() as u16);
+            data.extend_from_slice(&local);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&compressed);
+
+            let mut header = vec![0u8; 46];
+            LittleEndian::write_u32(&mut header[0..], CENTRAL_SIGNATURE);
+            LittleEndian::write_u16(&mut header[10..], *method);
+            LittleEndian::write_u32(&mut header[20..], compressed.len() as u32);
+            LittleEndian::write_u32(&mut header[24..], content.len() as u32);
+            LittleEndian::write_u16(&mut header[28..], name.len() as u16);
+            LittleEndian::write_u32(&mut header[42..], local_offset);
+            central.extend_from_slice(&header);
+            central.extend_from_slice(name.as_bytes());
+        }
+        let central_offset = data.len() as u32;
+        let mut eocd = vec![0u8; 22];
+        LittleEndian::write_u32(&mut eocd[0..], EOCD_SIGNATURE);
+        LittleEndian::write_u16(&mut eocd[8..], entries.len() as u16);
+        LittleEndian::write_u16(&mut eocd[10..], entries.len() as u16);
+        LittleEndian::write_u32(&mut eocd[12..], central.len() as u32);
+        LittleEndian::write_u32(&mut eocd[16..], central_offset);
+        data.extend_from_slice(&central);
+        data.extend_from_slice(&eocd);
+        data
+    }
+
+    #[test]
+    fn test_read_tar() {
+        let data = tar(&[
+            tar_entry("txs/", "", b'5', b""),
+            tar_entry("a.json", "", b'0', b"{}"),
+            tar_entry("b.json", "long/prefix", 0, &[1u8; 600]),
+        ]);
+        let files = read_tar(&data).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0], ("a.json".to_string(), b"{}".to_vec()));
+        assert_eq!(files[1].0, "long/prefix/b.json");
+        assert_eq!(files[1].1, vec![1u8; 600]);
+    }
+
+    #[test]
+    fn test_read_tar_empty_and_truncated() {
+        assert!(read_tar(&[]).unwrap().is_empty());
+        assert!(read_tar(&tar(&[])).unwrap().is_empty());
+        let mut data = tar_entry("a.json", "", b'0', &[1u8; 600]);
+        data.truncate(700);
+        assert!(read_tar(&data).is_err());
+    }
+
+    #[test]
+    fn test_read_zip() {
+        let data = zip(&[
+            ("txs/", 0, b""),
+            ("txs/a.json", 0, b"{}"),
+            ("txs/b.json", 8, &[2u8; 1000]),
+        ]);
+        let files = read_zip(&data).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0], ("txs/a.json".to_string(), b"{}".to_vec()));
+        assert_eq!(files[1], ("txs/b.json".to_string(), vec![2u8; 1000]));
+    }
+
+    #[test]
+    fn test_read_zip_empty_and_truncated() {
+        assert!(read_zip(&[]).is_err());
+        assert!(read_zip(&zip(&[])).unwrap().is_empty());
+        let data = zip(&[("a.json", 0, b"{}")]);
+        // Drop the content of the entry, the offsets point beyond the data
+        let truncated = [&data[..30], &data[data.len() - 22..]].concat();
+        assert!(read_zip(&truncated).is_err());
+        assert!(read_zip(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0u8; 4096]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(decompress(DeflateDecoder::new(compressed.as_slice()), 4096).is_ok());
+        assert!(decompress(DeflateDecoder::new(compressed.as_slice()), 4095).is_err());
+    }
+
+    #[test]
+    fn test_is_hidden() {
+        assert!(is_hidden("__MACOSX/._tx.json"));
+        assert!(is_hidden(".tx.json"));
+        assert!(is_hidden("txs/.hidden/tx.json"));
+        assert!(!is_hidden("txs/tx.json"));
+        assert!(!is_hidden("./tx.json"));
+    }
+}
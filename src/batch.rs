@@ -0,0 +1,81 @@
+//! Raw JSON-RPC calls: the methods not wrapped by the SDK client, and the
+//! batch request (send several independent read calls in one HTTP round trip).
+use anyhow::{anyhow, Error};
+use serde_json::{json, Value};
+
+/// The JSON-RPC error code of an unknown method
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Call the method by a single request, return the result
+pub fn call(url: &str, method: &str, params: Value) -> Result<Value, Error> {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "method": method,
+        "params": params,
+    });
+    let mut response: Value = reqwest::blocking::Client::new()
+        .post(url)
+        .json(&request)
+        .send()
+        .and_then(|resp| resp.json())
+        .map_err(|err| anyhow!("{} request error: {}", method, err))?;
+    if let Some(err) = response.get("error") {
+        return Err(rpc_error(url, method, err));
+    }
+    Ok(response["result"].take())
+}
+
+fn rpc_error(url: &str, method: &str, err: &Value) -> Error {
+    if err["code"].as_i64() == Some(METHOD_NOT_FOUND) {
+        anyhow!(
+            "the method {} is not supported by the node: {}",
+            method,
+            url
+        )
+    } else {
+        anyhow!("{} error: {}", method, err)
+    }
+}
+
+/// Send the calls `(method, params)` in one batch request, the results are
+/// returned in the order of the calls. Any failed call fails the whole batch.
+pub fn batch_call(url: &str, calls: &[(&str, Value)]) -> Result<Vec<Value>, Error> {
+    let requests: Vec<_> = calls
+        .iter()
+        .enumerate()
+        .map(|(id, (method, params))| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": method,
+                "params": params,
+            })
+        })
+        .collect();
+    let responses: Vec<Value> = reqwest::blocking::Client::new()
+        .post(url)
+        .json(&requests)
+        .send()
+        .and_then(|resp| resp.json())
+        .map_err(|err| anyhow!("batch request error: {}", err))?;
+    let mut results = vec![None; calls.len()];
+    for mut response in responses {
+        let id = response["id"]
+            .as_u64()
+            .map(|id| id as usize)
+            .filter(|id| *id < calls.len())
+            .ok_or_else(|| anyhow!("invalid batch response id: {}", response["id"]))?;
+        if let Some(err) = response.get("error") {
+            return Err(rpc_error(url, calls[id].0, err));
+        }
+        results[id] = Some(response["result"].take());
+    }
+    results
+        .into_iter()
+        .zip(calls)
+        .map(|(result, (method, _))| {
+            result.ok_or_else(|| anyhow!("missing batch response of {}", method))
+        })
+        .collect()
+}
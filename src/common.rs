@@ -1,6 +1,10 @@
+use std::io::{self, Write};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use ckb_types::H256;
+use anyhow::anyhow;
+use ckb_sdk::HumanCapacity;
+use ckb_types::{packed::OutPoint, prelude::*, H256};
 
 #[derive(Debug, Clone)]
 pub struct HexH256(pub H256);
@@ -20,3 +24,63 @@ pub fn remove0x(value: &str) -> &str {
         value
     }
 }
+
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Answer "yes" to all the confirmations of the current command, it's decided
+/// once by `main` according to whether the command is a read or a write.
+pub fn set_assume_yes(assume_yes: bool) {
+    ASSUME_YES.store(assume_yes, Ordering::SeqCst);
+}
+
+/// Parse the out-point from "{tx-hash}-{index}"
+pub fn parse_out_point(input: &str) -> Result<OutPoint, anyhow::Error> {
+    let parts = input.split('-').collect::<Vec<_>>();
+    if parts.len() != 2 {
+        return Err(anyhow!(
+            "Invalid OutPoint: {}, format: {{tx-hash}}-{{index}}",
+            input
+        ));
+    }
+    let tx_hash_str = remove0x(parts[0]);
+    let tx_hash: H256 = H256::from_str(tx_hash_str)?;
+    let index = u32::from_str(parts[1])?;
+    Ok(OutPoint::new(tx_hash.pack(), index))
+}
+
+/// Parse the capacity (unit: CKB) like `HumanCapacity`, the decimal part
+/// longer than 8 digits (below 1 shannon) is rejected with the rounded down
+/// value in the error message (the trailing zeros are allowed).
+pub fn parse_capacity(input: &str) -> Result<HumanCapacity, String> {
+    let input = input.trim();
+    let (integer, decimal) = input.split_once('.').unwrap_or((input, ""));
+    if decimal.len() <= 8 {
+        return HumanCapacity::from_str(input);
+    }
+    let (kept, dropped) = decimal.split_at(8);
+    if !dropped.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("invalid decimal part: {}", decimal));
+    }
+    let capacity = HumanCapacity::from_str(&format!("{}.{}", integer, kept))?;
+    if dropped.chars().any(|c| c != '0') {
+        return Err(format!(
+            "the capacity {} is below the shannon precision (8 decimals), use {} instead",
+            input, capacity
+        ));
+    }
+    Ok(capacity)
+}
+
+/// Ask the user to confirm (type "yes" or "y") before continue
+pub fn confirm(prompt: &str) -> Result<bool, anyhow::Error> {
+    if ASSUME_YES.load(Ordering::SeqCst) {
+        println!("{} (yes/no): yes (assumed)", prompt);
+        return Ok(true);
+    }
+    print!("{} (yes/no): ", prompt);
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let input = input.trim().to_lowercase();
+    Ok(input == "yes" || input == "y")
+}
@@ -1,5 +1,6 @@
-use std::collections::HashMap;
-use std::str::FromStr;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::thread;
 use std::time::Duration;
 
@@ -7,42 +8,51 @@ use anyhow::{anyhow, Error};
 use byteorder::{ByteOrder, LittleEndian};
 use ckb_jsonrpc_types as json_types;
 use ckb_sdk::{
-    constants::{DAO_TYPE_HASH, SIGHASH_TYPE_HASH},
-    rpc::LightClientRpcClient,
+    constants::DAO_TYPE_HASH,
+    rpc::{
+        ckb_light_client::{Order, SearchKey},
+        LightClientRpcClient,
+    },
     traits::{
-        CellCollector, CellQueryOptions, DefaultCellDepResolver, LightClientCellCollector,
-        LightClientHeaderDepResolver, LightClientTransactionDependencyProvider, LiveCell, Signer,
-        ValueRangeOption,
+        CellCollector, CellQueryOptions, LightClientCellCollector, LightClientHeaderDepResolver,
+        LightClientTransactionDependencyProvider, LiveCell, ValueRangeOption,
     },
     tx_builder::{
         dao::{
             DaoDepositBuilder, DaoDepositReceiver, DaoPrepareBuilder, DaoPrepareItem,
             DaoWithdrawBuilder, DaoWithdrawItem, DaoWithdrawReceiver,
         },
-        CapacityBalancer, CapacityProvider, TxBuilder,
+        unlock_tx, CapacityBalancer, CapacityProvider, TxBuilder,
     },
-    unlock::{ScriptUnlocker, SecpSighashScriptSigner, SecpSighashUnlocker},
-    Address, HumanCapacity, ScriptId,
+    util::{calculate_dao_maximum_withdraw4, minimal_unlock_point},
+    Address, HumanCapacity,
 };
 use ckb_types::{
-    bytes::Bytes,
-    core::{FeeRate, ScriptHashType},
-    packed::{CellInput, OutPoint, Script, WitnessArgs},
+    core::{
+        Capacity, EpochNumberWithFraction, FeeRate, HeaderView, ScriptHashType, TransactionView,
+    },
+    packed::{CellInput, CellOutput, OutPoint, Script, Transaction},
     prelude::*,
     H256,
 };
 use clap::{ArgGroup, Subcommand};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::common::remove0x;
-use crate::wallet::{check_address, get_signer};
+use crate::common::{parse_capacity, parse_out_point};
+use crate::output::Output;
+use crate::poll::{wait_epoch, wait_tx_committed, POLL_INTERVAL};
+use crate::util::get_any_transaction;
+use crate::wallet::{
+    build_unlockers, check_address, get_cell_dep_resolver, get_since_source, placeholder_witness,
+    send_with_fee_bump, BuildArgs, Unlockers, DEFAULT_FEE_RATE,
+};
 
 #[derive(Subcommand, Debug)]
 pub enum DaoCommands {
     /// Deposit capacity into NervosDAO
     #[command(group(ArgGroup::new("from").required(true).args(["from_address", "from_key"])))]
     Deposit {
-        /// The sender address (sighash only, also used to match key in ckb-cli keystore)
+        /// The sender address (sighash/multisig, also used to match key in ckb-cli keystore)
         #[arg(long, value_name = "ADDR")]
         from_address: Option<Address>,
 
@@ -50,14 +60,30 @@ pub enum DaoCommands {
         #[arg(long, value_name = "PRIVKEY")]
         from_key: Option<H256>,
 
+        /// The multisig config file (required when <from-address> is a multisig address)
+        #[arg(long, value_name = "FILE", requires = "from_address")]
+        multisig_config: Option<PathBuf>,
+
+        #[command(flatten)]
+        build_args: BuildArgs,
+
         /// The capacity to deposit (unit: CKB, example: 102.43)
-        #[arg(long, value_name = "CAPACITY")]
+        #[arg(long, value_name = "CAPACITY", value_parser = parse_capacity)]
         capacity: HumanCapacity,
+
+        /// After the deposit transaction committed, write the deposit receipt (out-point, capacity, block number, block hash and timestamp) to the file
+        #[arg(long, value_name = "FILE")]
+        receipt: Option<PathBuf>,
+
+        /// Explain what the operation does (with the involved cells/amounts)
+        /// before acting
+        #[arg(long)]
+        explain_dao: bool,
     },
     /// Prepare specified cells from NervosDAO
     #[command(group(ArgGroup::new("from").required(true).args(["from_address", "from_key"])))]
     Prepare {
-        /// The sender address (sighash only, also used to match key in ckb-cli keystore)
+        /// The sender address (sighash/multisig, also used to match key in ckb-cli keystore)
         #[arg(long, value_name = "ADDR")]
         from_address: Option<Address>,
 
@@ -65,14 +91,44 @@ pub enum DaoCommands {
         #[arg(long, value_name = "PRIVKEY")]
         from_key: Option<H256>,
 
+        /// The multisig config file (required when <from-address> is a multisig address)
+        #[arg(long, value_name = "FILE", requires = "from_address")]
+        multisig_config: Option<PathBuf>,
+
+        #[command(flatten)]
+        build_args: BuildArgs,
+
         #[arg(long, value_name = "OUT-POINT")]
         /// out-point to specify a cell. Example: 0xd56ed5d4e8984701714de9744a533413f79604b3b91461e2265614829d2005d1-1
         out_points: Vec<String>,
+
+        /// The out-points file, a JSON array of out-point objects. Example:
+        /// [{"tx_hash": "0xd56e...05d1", "index": "0x1"}]
+        #[arg(long, value_name = "FILE")]
+        out_points_json: Option<PathBuf>,
+
+        /// After the prepare transaction committed, wait until the tip epoch advanced <N> epochs
+        #[arg(long, value_name = "N")]
+        wait_epochs: Option<u64>,
+
+        /// After the prepare transaction committed, wait until the prepared cells are withdrawable
+        #[arg(long, conflicts_with = "wait_epochs")]
+        wait_withdrawable: bool,
+
+        /// Print the header deps (block hash, number, epoch and the DAO `ar`)
+        /// of the built transaction, they anchor the NervosDAO reward computation
+        #[arg(long)]
+        show_header_deps: bool,
+
+        /// Explain what the operation does (with the involved cells/amounts)
+        /// before acting
+        #[arg(long)]
+        explain_dao: bool,
     },
     /// Withdraw specified cells from NervosDAO
     #[command(group(ArgGroup::new("from").required(true).args(["from_address", "from_key"])))]
     Withdraw {
-        /// The sender address (sighash only, also used to match key in ckb-cli keystore)
+        /// The sender address (sighash/multisig, also used to match key in ckb-cli keystore)
         #[arg(long, value_name = "ADDR")]
         from_address: Option<Address>,
 
@@ -80,112 +136,387 @@ pub enum DaoCommands {
         #[arg(long, value_name = "PRIVKEY")]
         from_key: Option<H256>,
 
+        /// The multisig config file (required when <from-address> is a multisig address)
+        #[arg(long, value_name = "FILE", requires = "from_address")]
+        multisig_config: Option<PathBuf>,
+
+        #[command(flatten)]
+        build_args: BuildArgs,
+
         #[arg(long, value_name = "OUT-POINT")]
         /// out-point to specify a cell. Example: 0xd56ed5d4e8984701714de9744a533413f79604b3b91461e2265614829d2005d1-1
         out_points: Vec<String>,
+
+        /// The out-points file, a JSON array of out-point objects. Example:
+        /// [{"tx_hash": "0xd56e...05d1", "index": "0x1"}]
+        #[arg(long, value_name = "FILE")]
+        out_points_json: Option<PathBuf>,
+
+        /// Print the header deps (block hash, number, epoch and the DAO `ar`)
+        /// of the built transaction, they anchor the NervosDAO reward computation
+        #[arg(long)]
+        show_header_deps: bool,
+
+        /// Explain what the operation does (with the involved cells/amounts)
+        /// before acting
+        #[arg(long)]
+        explain_dao: bool,
+    },
+    /// Verify the deposit receipt (written by `deposit --receipt`) against the chain,
+    /// and report the current accrued reward of the deposit
+    VerifyReceipt {
+        /// The deposit receipt file
+        #[arg(long, value_name = "FILE")]
+        receipt: PathBuf,
     },
     /// Query NervosDAO deposited capacity by address
     QueryDepositedCells {
         #[arg(long, value_name = "ADDR")]
         address: Address,
+
+        /// Stop collecting after <N> cells (the result will be truncated)
+        #[arg(long, value_name = "N")]
+        max_cells: Option<usize>,
+
+        /// Also sum the deposited capacity by the epoch of the deposit block
+        /// (fetches the header of each deposit transaction)
+        #[arg(long)]
+        group_by_epoch: bool,
     },
     /// Query NervosDAO prepared capacity by address
     QueryPreparedCells {
         #[arg(long, value_name = "ADDR")]
         address: Address,
+
+        /// Stop collecting after <N> cells (the result will be truncated)
+        #[arg(long, value_name = "N")]
+        max_cells: Option<usize>,
     },
+    /// Report the network-wide NervosDAO figures from the `dao` field of the
+    /// tip header (total issuance, occupied capacity, accumulated rate)
+    NetworkStats,
+}
+
+impl DaoCommands {
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            DaoCommands::Deposit { .. }
+                | DaoCommands::Prepare { .. }
+                | DaoCommands::Withdraw { .. }
+        )
+    }
 }
 
-pub fn invoke(rpc_url: &str, cmd: DaoCommands, debug: bool) -> Result<(), Error> {
+pub fn invoke(rpc_url: &str, cmd: DaoCommands, output: &Output, debug: bool) -> Result<(), Error> {
     match cmd {
         DaoCommands::Deposit {
             from_address,
             from_key,
+            multisig_config,
+            build_args,
             capacity,
+            receipt,
+            explain_dao,
         } => {
-            let (sender, signer) = get_signer(from_address, from_key)?;
+            let (sender, signer, multisig_config) =
+                build_args.get_signer(from_address, from_key, multisig_config.as_deref())?;
+            if explain_dao {
+                println!(
+                    "NervosDAO deposit: {} is locked in a new DAO cell of the sender, the cell \
+                     earns the compensation from the secondary issuance. To get it back, \
+                     `dao prepare` the cell, then `dao withdraw` it when the lock period \
+                     (180-epoch cycles, about 30 days each, from the deposit) ends.",
+                    output.format_capacity_with_unit(capacity.0)
+                );
+            }
             let deposit_receiver = DaoDepositReceiver::new(sender.clone(), capacity.0);
             let tx_builder = DaoDepositBuilder::new(vec![deposit_receiver]);
-            build_and_send_dao_tx(&tx_builder, sender, signer, rpc_url, debug)?;
+            let tx_hash = build_and_send_dao_tx(
+                &|_| Box::new(tx_builder.clone()),
+                sender.clone(),
+                build_unlockers(signer, multisig_config),
+                DaoBuildOptions {
+                    build_args: &build_args,
+                    show_header_deps: false,
+                },
+                rpc_url,
+                output,
+                debug,
+            )?;
+            if let (Some(path), Some(tx_hash)) = (receipt, tx_hash) {
+                let receipt = wait_deposit_receipt(rpc_url, &tx_hash, &sender, capacity.0)?;
+                fs::write(&path, serde_json::to_string_pretty(&receipt)?)?;
+                println!("deposit receipt written to {}", path.display());
+            }
         }
         DaoCommands::Prepare {
             from_address,
             from_key,
+            multisig_config,
+            build_args,
             out_points,
+            out_points_json,
+            wait_epochs,
+            wait_withdrawable,
+            show_header_deps,
+            explain_dao,
         } => {
-            let (sender, signer) = get_signer(from_address, from_key)?;
-            let items = parse_out_points(out_points)?
-                .into_iter()
-                .map(|out_point| DaoPrepareItem::from(CellInput::new(out_point, 0)))
+            let (sender, signer, multisig_config) =
+                build_args.get_signer(from_address, from_key, multisig_config.as_deref())?;
+            let out_points = parse_out_points(out_points, out_points_json.as_deref())?;
+            if explain_dao {
+                println!(
+                    "NervosDAO prepare (phase 1 of the withdrawal): the deposited cells below \
+                     become prepared cells, the compensation stops accruing at the prepare \
+                     block. The prepared cells can be withdrawn by `dao withdraw` when the \
+                     current 180-epoch cycle of each deposit ends."
+                );
+                explain_dao_cells(rpc_url, &out_points, output)?;
+            }
+            let items = out_points
+                .iter()
+                .map(|out_point| DaoPrepareItem::from(CellInput::new(out_point.clone(), 0)))
                 .collect();
             let tx_builder = DaoPrepareBuilder::new(items);
-            build_and_send_dao_tx(&tx_builder, sender, signer, rpc_url, debug)?;
+            let tx_hash = build_and_send_dao_tx(
+                &|_| Box::new(tx_builder.clone()),
+                sender,
+                build_unlockers(signer, multisig_config),
+                DaoBuildOptions {
+                    build_args: &build_args,
+                    show_header_deps,
+                },
+                rpc_url,
+                output,
+                debug,
+            )?;
+            match tx_hash {
+                Some(tx_hash) if wait_epochs.is_some() || wait_withdrawable => {
+                    wait_prepared(rpc_url, &tx_hash, &out_points, wait_epochs)?;
+                }
+                _ => {}
+            }
         }
         DaoCommands::Withdraw {
             from_address,
             from_key,
+            multisig_config,
+            build_args,
             out_points,
+            out_points_json,
+            show_header_deps,
+            explain_dao,
         } => {
-            let (sender, signer) = get_signer(from_address, from_key)?;
-            let mut items: Vec<_> = parse_out_points(out_points)?
+            let (sender, signer, multisig_config) =
+                build_args.get_signer(from_address, from_key, multisig_config.as_deref())?;
+            let out_points = parse_out_points(out_points, out_points_json.as_deref())?;
+            if explain_dao {
+                println!(
+                    "NervosDAO withdraw (phase 2 of the withdrawal): the prepared cells below \
+                     are spent, the deposited capacity plus the compensation go back to the \
+                     sender (the fee is paid from it). It's only valid after the lock period \
+                     of each deposit ended."
+                );
+                explain_dao_cells(rpc_url, &out_points, output)?;
+            }
+            let mut items: Vec<_> = out_points
                 .into_iter()
                 .map(|out_point| DaoWithdrawItem::new(out_point, None))
                 .collect();
-            items[0].init_witness = Some(
-                WitnessArgs::new_builder()
-                    .lock(Some(Bytes::from(vec![0u8; 65])).pack())
-                    .build(),
-            );
-            let receiver = DaoWithdrawReceiver::LockScript {
-                script: sender.clone(),
-                fee_rate: Some(FeeRate::from_u64(1000)),
+            let unlockers = build_unlockers(signer, multisig_config);
+            items[0].init_witness = Some(placeholder_witness(&unlockers, &sender)?);
+            // The fee of withdraw transaction is paid by the withdrawn capacity
+            let receiver_lock = sender.clone();
+            let build_tx_builder = |fee_rate: u64| -> Box<dyn TxBuilder> {
+                let receiver = DaoWithdrawReceiver::LockScript {
+                    script: receiver_lock.clone(),
+                    fee_rate: Some(FeeRate::from_u64(fee_rate)),
+                };
+                Box::new(DaoWithdrawBuilder::new(items.clone(), receiver))
             };
-            let tx_builder = DaoWithdrawBuilder::new(items, receiver);
-            build_and_send_dao_tx(&tx_builder, sender, signer, rpc_url, debug)?;
+            build_and_send_dao_tx(
+                &build_tx_builder,
+                sender,
+                unlockers,
+                DaoBuildOptions {
+                    build_args: &build_args,
+                    show_header_deps,
+                },
+                rpc_url,
+                output,
+                debug,
+            )?;
         }
-        DaoCommands::QueryDepositedCells { address } => {
-            let cells = query_dao_cells(rpc_url, &address, true)?;
+        DaoCommands::VerifyReceipt { receipt } => {
+            let content = fs::read_to_string(&receipt)?;
+            let receipt: DepositReceipt = serde_json::from_str(&content)
+                .map_err(|err| anyhow!("invalid deposit receipt {}: {}", receipt.display(), err))?;
+            output.print_json(&verify_deposit_receipt(rpc_url, &receipt, output)?)?;
+        }
+        DaoCommands::QueryDepositedCells {
+            address,
+            max_cells,
+            group_by_epoch,
+        } => {
+            check_address(
+                &mut LightClientRpcClient::new(rpc_url),
+                Script::from(&address).into(),
+            )?;
+            let cells = query_dao_cells(rpc_url, &address, true, max_cells)?;
+            let epochs = if group_by_epoch {
+                Some(group_by_deposit_epoch(rpc_url, &cells)?)
+            } else {
+                None
+            };
+            let cells: Vec<_> = cells.iter().map(to_live_cell_info).collect();
             let total_capacity = cells.iter().map(|info| info.capacity).sum::<u64>();
-            println!(
-                "{}",
-                serde_json::to_string_pretty(&serde_json::json!({
-                    "live_cells": cells,
-                    "total_capacity": total_capacity,
-                }))
-                .unwrap()
-            );
+            print_dao_cells(output, cells, total_capacity, epochs)?;
         }
-        DaoCommands::QueryPreparedCells { address } => {
-            let cells = query_dao_cells(rpc_url, &address, false)?;
+        DaoCommands::QueryPreparedCells { address, max_cells } => {
+            check_address(
+                &mut LightClientRpcClient::new(rpc_url),
+                Script::from(&address).into(),
+            )?;
+            let cells = query_dao_cells(rpc_url, &address, false, max_cells)?;
+            let cells: Vec<_> = cells.iter().map(to_live_cell_info).collect();
             let total_capacity = cells.iter().map(|info| info.capacity).sum::<u64>();
-            println!(
-                "{}",
-                serde_json::to_string_pretty(&serde_json::json!({
-                    "live_cells": cells,
-                    "total_capacity": total_capacity,
-                }))
-                .unwrap()
-            );
+            print_dao_cells(output, cells, total_capacity, None)?;
+        }
+        DaoCommands::NetworkStats => {
+            let mut client = LightClientRpcClient::new(rpc_url);
+            let tip_header = HeaderView::from(client.get_tip_header()?);
+            let dao = DaoField::from_header(&tip_header);
+            let tip_hash: H256 = tip_header.hash().unpack();
+            output.print_json(&serde_json::json!({
+                "tip_number": tip_header.number(),
+                "tip_hash": tip_hash,
+                "epoch": tip_header.epoch().to_string(),
+                "total_issuance": output.format_capacity(dao.c),
+                "unissued_secondary_issuance": output.format_capacity(dao.s),
+                "occupied_capacity": output.format_capacity(dao.u),
+                "free_capacity": output.format_capacity(dao.c.saturating_sub(dao.u)),
+                "occupied_ratio": format!("{:.4}", dao.u as f64 / dao.c as f64),
+                "accumulated_rate": dao.ar,
+                "accumulated_rate_ratio": dao.ar_ratio(),
+            }))?;
         }
     }
     Ok(())
 }
 
+fn print_dao_cells(
+    output: &Output,
+    cells: Vec<LiveCellInfo>,
+    total_capacity: u64,
+    epochs: Option<Vec<EpochDeposits>>,
+) -> Result<(), Error> {
+    let mut result = serde_json::json!({
+        "live_cells": cells,
+        "total_capacity": total_capacity,
+    });
+    if let Some(usd) = output.usd_value(total_capacity) {
+        result["total_usd"] = usd.into();
+    }
+    if let Some(epochs) = epochs {
+        result["epochs"] = serde_json::to_value(epochs)?;
+    }
+    output.print_json(&result)
+}
+
+/// Print the capacity and the block number of the DAO cells for `--explain-dao`
+fn explain_dao_cells(rpc_url: &str, out_points: &[OutPoint], output: &Output) -> Result<(), Error> {
+    let mut client = LightClientRpcClient::new(rpc_url);
+    for out_point in out_points {
+        let tx_hash: H256 = out_point.tx_hash().unpack();
+        let index: u32 = out_point.index().unpack();
+        let tx_with_header = get_any_transaction(&mut client, tx_hash.clone())?;
+        let tx = Transaction::from(tx_with_header.transaction.inner).into_view();
+        let cell = tx
+            .output(index as usize)
+            .ok_or_else(|| anyhow!("cell not found: {:#x}-{}", tx_hash, index))?;
+        let capacity: u64 = cell.capacity().unpack();
+        println!(
+            "  {:#x}-{}: {}, in block {}",
+            tx_hash,
+            index,
+            output.format_capacity_with_unit(capacity),
+            tx_with_header.header.inner.number.value()
+        );
+    }
+    Ok(())
+}
+
+/// The deposited cells of an epoch
+#[derive(Serialize)]
+struct EpochDeposits {
+    epoch: u64,
+    cells: usize,
+    capacity: u64,
+}
+
+/// Bucket the deposited cells by the epoch number of the deposit block
+fn group_by_deposit_epoch(rpc_url: &str, cells: &[LiveCell]) -> Result<Vec<EpochDeposits>, Error> {
+    let mut client = LightClientRpcClient::new(rpc_url);
+    // Several deposit cells may be in the same transaction
+    let mut tx_epochs: BTreeMap<H256, u64> = BTreeMap::new();
+    let mut epochs: BTreeMap<u64, (usize, u64)> = BTreeMap::new();
+    for cell in cells {
+        let tx_hash: H256 = cell.out_point.tx_hash().unpack();
+        let epoch = match tx_epochs.get(&tx_hash) {
+            Some(epoch) => *epoch,
+            None => {
+                let header =
+                    HeaderView::from(get_any_transaction(&mut client, tx_hash.clone())?.header);
+                let epoch = header.epoch().number();
+                tx_epochs.insert(tx_hash, epoch);
+                epoch
+            }
+        };
+        let capacity: u64 = cell.output.capacity().unpack();
+        let entry = epochs.entry(epoch).or_default();
+        entry.0 += 1;
+        entry.1 += capacity;
+    }
+    Ok(epochs
+        .into_iter()
+        .map(|(epoch, (cells, capacity))| EpochDeposits {
+            epoch,
+            cells,
+            capacity,
+        })
+        .collect())
+}
+
+/// The shared build arguments and the DAO only build options
+struct DaoBuildOptions<'a> {
+    build_args: &'a BuildArgs,
+    show_header_deps: bool,
+}
+
+/// Build (the tx builder is created by the fee rate) and send the DAO
+/// transaction, return the transaction hash (None if it's a dry run).
 fn build_and_send_dao_tx(
-    builder: &dyn TxBuilder,
+    build_tx_builder: &dyn Fn(u64) -> Box<dyn TxBuilder>,
     sender: Script,
-    signer: Box<dyn Signer>,
+    unlockers: Unlockers,
+    options: DaoBuildOptions,
     rpc_url: &str,
+    output: &Output,
     debug: bool,
-) -> Result<(), Error> {
-    let balancer = CapacityBalancer {
-        fee_rate: FeeRate::from_u64(1000),
+) -> Result<Option<H256>, Error> {
+    let DaoBuildOptions {
+        build_args,
+        show_header_deps,
+    } = options;
+    let placeholder_witness = placeholder_witness(&unlockers, &sender)?;
+    let mut balancer = CapacityBalancer {
+        fee_rate: FeeRate::from_u64(DEFAULT_FEE_RATE),
         change_lock_script: None,
-        capacity_provider: CapacityProvider::new_simple(vec![(
+        capacity_provider: CapacityProvider::new(vec![(
             sender.clone(),
-            WitnessArgs::new_builder()
-                .lock(Some(Bytes::from(vec![0u8; 65])).pack())
-                .build(),
+            placeholder_witness,
+            get_since_source(&sender),
         )]),
         force_small_change_as_fee: None,
     };
@@ -195,85 +526,429 @@ fn build_and_send_dao_tx(
     println!("tip number: {}", cells_capacity.block_number.value());
     println!("tip hash: {:#x}", cells_capacity.block_hash);
 
-    let script_id = ScriptId::new_type(SIGHASH_TYPE_HASH.clone());
-    let sighash_unlocker = SecpSighashUnlocker::new(SecpSighashScriptSigner::new(signer));
-    let mut unlockers: HashMap<_, Box<dyn ScriptUnlocker>> = HashMap::new();
-    unlockers.insert(script_id, Box::new(sighash_unlocker));
-
     // Build:
     //   * CellDepResolver
     //   * HeaderDepResolver
     //   * CellCollector
     //   * TransactionDependencyProvider
-    let genesis_block = client.get_genesis_block()?.into();
-    let cell_dep_resolver = DefaultCellDepResolver::from_genesis(&genesis_block)?;
+    let cell_dep_resolver = get_cell_dep_resolver(&mut client, build_args)?;
     let header_dep_resolver = LightClientHeaderDepResolver::new(rpc_url);
     let tx_dep_provider = LightClientTransactionDependencyProvider::new(rpc_url);
-    let mut cell_collector = LightClientCellCollector::new(rpc_url);
-
-    let mut retry = 0;
-    let (tx, still_locked_groups) = loop {
-        match builder.build_unlocked(
-            &mut cell_collector,
-            &cell_dep_resolver,
-            &header_dep_resolver,
-            &tx_dep_provider,
-            &balancer,
-            &unlockers,
-        ) {
-            Ok((tx, still_locked_groups)) => {
-                break (tx, still_locked_groups);
-            }
-            Err(err) => {
-                if header_dep_resolver.is_ready() && tx_dep_provider.is_ready() {
-                    return Err(err.into());
-                } else {
-                    if retry == 10 {
-                        return Err(anyhow!("retry 10 times, error: {}", err));
-                    }
-                    if debug {
-                        println!("error: {}, sleep 0.5 seconds (retry={}).....", retry, err);
+
+    send_with_fee_bump(rpc_url, build_args, debug, |fee_rate| {
+        balancer.fee_rate = FeeRate::from_u64(fee_rate);
+        let builder = build_tx_builder(fee_rate);
+        let mut cell_collector = LightClientCellCollector::new(rpc_url);
+        let mut retry = 0;
+        let tx = loop {
+            match builder.build_balanced(
+                &mut cell_collector,
+                cell_dep_resolver.as_ref(),
+                &header_dep_resolver,
+                &tx_dep_provider,
+                &balancer,
+                &unlockers,
+            ) {
+                Ok(tx) => {
+                    break tx;
+                }
+                Err(err) => {
+                    if header_dep_resolver.is_ready() && tx_dep_provider.is_ready() {
+                        return Err(err.into());
+                    } else {
+                        if retry == 10 {
+                            return Err(anyhow!("retry 10 times, error: {}", err));
+                        }
+                        if debug {
+                            println!("error: {}, sleep 0.5 seconds (retry={}).....", retry, err);
+                        }
+                        thread::sleep(Duration::from_millis(500));
+                        retry += 1;
+                        continue;
                     }
-                    thread::sleep(Duration::from_millis(500));
-                    retry += 1;
-                    continue;
                 }
             }
+        };
+        if build_args.is_dry_run() {
+            // Trace the unsigned transaction (with the placeholder witnesses)
+            build_args.show_inputs(&tx, &tx_dep_provider)?;
+            if show_header_deps {
+                print_header_deps(&mut client, &tx)?;
+            }
+            build_args.trace(&mut client, &tx, &tx_dep_provider, fee_rate, output)?;
+            return Ok(tx);
         }
-    };
-    assert!(still_locked_groups.is_empty());
-    // Send transaction
-    let json_tx = json_types::TransactionView::from(tx);
-    if debug {
-        println!("tx: {}", serde_json::to_string_pretty(&json_tx).unwrap());
+        let (tx, still_locked_groups) = unlock_tx(tx, &tx_dep_provider, &unlockers)?;
+        assert!(still_locked_groups.is_empty());
+        build_args.show_inputs(&tx, &tx_dep_provider)?;
+        if show_header_deps {
+            print_header_deps(&mut client, &tx)?;
+        }
+        build_args.dump_sighash(&tx, &tx_dep_provider)?;
+        build_args.export_ckb_cli_tx(&mut client, &tx, &tx_dep_provider)?;
+        build_args.dump_fixture(&mut client, &tx, &tx_dep_provider)?;
+        Ok(tx)
+    })
+}
+
+/// Print the block hash, number, epoch and the DAO `ar` of the header deps
+fn print_header_deps(client: &mut LightClientRpcClient, tx: &TransactionView) -> Result<(), Error> {
+    let mut headers = Vec::new();
+    for block_hash in tx.header_deps_iter() {
+        let block_hash: H256 = block_hash.unpack();
+        let header = client
+            .get_header(block_hash.clone())?
+            .ok_or_else(|| anyhow!("header dep not found: {:#x}", block_hash))?;
+        let header = HeaderView::from(header);
+        let dao = DaoField::from_header(&header);
+        headers.push(serde_json::json!({
+            "block_hash": block_hash,
+            "number": header.number(),
+            "epoch": header.epoch().to_string(),
+            "ar": dao.ar,
+            "ar_ratio": dao.ar_ratio(),
+        }));
     }
-    let tx_hash = client
-        .send_transaction(json_tx.inner)
-        .expect("send transaction");
-    println!(">>> tx sent! {:#x} <<<", tx_hash);
+    println!(
+        "header deps: {}",
+        serde_json::to_string_pretty(&headers).unwrap()
+    );
     Ok(())
 }
 
-fn parse_out_points(out_points: Vec<String>) -> Result<Vec<OutPoint>, Error> {
-    if out_points.is_empty() {
-        return Err(anyhow!("missing out points"));
+/// The `dao` field of the block header, see the NervosDAO RFC
+pub struct DaoField {
+    /// The total issuance up to and including this block
+    pub c: u64,
+    /// The accumulated rate (fixed point, `DAO_AR_BASE` is 1.0)
+    pub ar: u64,
+    /// The total unissued secondary issuance
+    pub s: u64,
+    /// The total occupied capacity (in shannons) up to and including this block
+    pub u: u64,
+}
+
+/// The initial accumulated rate
+pub const DAO_AR_BASE: u64 = 10_000_000_000_000_000;
+
+impl DaoField {
+    pub fn from_header(header: &HeaderView) -> DaoField {
+        let data = header.dao().raw_data();
+        DaoField {
+            c: LittleEndian::read_u64(&data[0..8]),
+            ar: LittleEndian::read_u64(&data[8..16]),
+            s: LittleEndian::read_u64(&data[16..24]),
+            u: LittleEndian::read_u64(&data[24..32]),
+        }
     }
-    out_points
+
+    /// The accumulated rate as a decimal, e.g. "1.0123456789012345"
+    pub fn ar_ratio(&self) -> String {
+        format!("{}.{:016}", self.ar / DAO_AR_BASE, self.ar % DAO_AR_BASE)
+    }
+}
+
+pub fn is_dao_output(output: &CellOutput) -> bool {
+    output
+        .type_()
+        .to_opt()
+        .map(|type_script| type_script.code_hash().as_slice() == DAO_TYPE_HASH.as_bytes())
+        .unwrap_or(false)
+}
+
+/// The record of a committed deposit, everything needed by prepare/withdraw
+/// and the reward computation.
+#[derive(Serialize, Deserialize)]
+struct DepositReceipt {
+    out_point: json_types::OutPoint,
+    capacity: json_types::Capacity,
+    block_number: json_types::BlockNumber,
+    block_hash: H256,
+    timestamp: json_types::Timestamp,
+}
+
+/// Wait for the deposit transaction committed, then locate the deposit cell
+/// (the DAO output locked by `sender` with `capacity`).
+fn wait_deposit_receipt(
+    rpc_url: &str,
+    deposit_tx_hash: &H256,
+    sender: &Script,
+    capacity: u64,
+) -> Result<DepositReceipt, Error> {
+    let mut client = LightClientRpcClient::new(rpc_url);
+    let tx_with_header = wait_tx_committed(&mut client, deposit_tx_hash, POLL_INTERVAL)?;
+    let header = HeaderView::from(tx_with_header.header);
+    let tx = Transaction::from(tx_with_header.transaction.inner);
+    let index = tx
+        .raw()
+        .outputs()
         .into_iter()
-        .map(|input| {
-            let parts = input.split('-').collect::<Vec<_>>();
-            if parts.len() != 2 {
-                return Err(anyhow!(
-                    "Invalid OutPoint: {}, format: {{tx-hash}}-{{index}}",
-                    input
-                ));
-            }
-            let tx_hash_str = remove0x(parts[0]);
-            let tx_hash: H256 = H256::from_str(tx_hash_str)?;
-            let index = u32::from_str(parts[1])?;
-            Ok(OutPoint::new(tx_hash.pack(), index))
+        .position(|output| {
+            let output_capacity: u64 = output.capacity().unpack();
+            output.lock() == *sender && output_capacity == capacity && is_dao_output(&output)
         })
-        .collect::<Result<Vec<_>, Error>>()
+        .ok_or_else(|| {
+            anyhow!(
+                "deposit cell not found in transaction {:#x}",
+                deposit_tx_hash
+            )
+        })?;
+    Ok(DepositReceipt {
+        out_point: OutPoint::new(deposit_tx_hash.pack(), index as u32).into(),
+        capacity: capacity.into(),
+        block_number: header.number().into(),
+        block_hash: header.hash().unpack(),
+        timestamp: header.timestamp().into(),
+    })
+}
+
+#[derive(Serialize)]
+struct VerifiedDeposit {
+    out_point: json_types::OutPoint,
+    capacity: json_types::Capacity,
+    block_number: json_types::BlockNumber,
+    tip_number: json_types::BlockNumber,
+    /// The maximum withdraw capacity if the deposit is prepared at the tip block
+    maximum_withdraw: json_types::Capacity,
+    accrued_reward: String,
+}
+
+/// Check the deposit receipt against the deposit transaction and its block
+/// header, then compute the reward accrued until the tip block.
+fn verify_deposit_receipt(
+    rpc_url: &str,
+    receipt: &DepositReceipt,
+    output: &Output,
+) -> Result<VerifiedDeposit, Error> {
+    let mut client = LightClientRpcClient::new(rpc_url);
+    let out_point = OutPoint::from(receipt.out_point.clone());
+    let tx_hash: H256 = out_point.tx_hash().unpack();
+    let index: u32 = out_point.index().unpack();
+    let tx_with_header = get_any_transaction(&mut client, tx_hash.clone())?;
+    let deposit_header = HeaderView::from(tx_with_header.header);
+    let tx = Transaction::from(tx_with_header.transaction.inner).into_view();
+    let (cell_output, data) = tx
+        .output_with_data(index as usize)
+        .ok_or_else(|| anyhow!("output not found: {:#x}-{}", tx_hash, index))?;
+
+    if !is_dao_output(&cell_output) || data.len() != 8 || LittleEndian::read_u64(&data) != 0 {
+        return Err(anyhow!(
+            "{:#x}-{} is not a NervosDAO deposit cell",
+            tx_hash,
+            index
+        ));
+    }
+    let capacity: u64 = cell_output.capacity().unpack();
+    let mut mismatches = Vec::new();
+    if capacity != receipt.capacity.value() {
+        mismatches.push(format!(
+            "capacity: receipt={}, chain={}",
+            receipt.capacity.value(),
+            capacity
+        ));
+    }
+    if deposit_header.number() != receipt.block_number.value() {
+        mismatches.push(format!(
+            "block number: receipt={}, chain={}",
+            receipt.block_number.value(),
+            deposit_header.number()
+        ));
+    }
+    let block_hash: H256 = deposit_header.hash().unpack();
+    if block_hash != receipt.block_hash {
+        mismatches.push(format!(
+            "block hash: receipt={:#x}, chain={:#x}",
+            receipt.block_hash, block_hash
+        ));
+    }
+    if deposit_header.timestamp() != receipt.timestamp.value() {
+        mismatches.push(format!(
+            "timestamp: receipt={}, chain={}",
+            receipt.timestamp.value(),
+            deposit_header.timestamp()
+        ));
+    }
+    if !mismatches.is_empty() {
+        return Err(anyhow!(
+            "the deposit receipt does not match the chain:\n  {}",
+            mismatches.join("\n  ")
+        ));
+    }
+
+    let tip_header = HeaderView::from(client.get_tip_header()?);
+    let occupied_capacity = cell_output
+        .occupied_capacity(Capacity::bytes(data.len())?)?
+        .as_u64();
+    let maximum_withdraw = calculate_dao_maximum_withdraw4(
+        &deposit_header,
+        &tip_header,
+        &cell_output,
+        occupied_capacity,
+    );
+    Ok(VerifiedDeposit {
+        out_point: receipt.out_point.clone(),
+        capacity: capacity.into(),
+        block_number: deposit_header.number().into(),
+        tip_number: tip_header.number().into(),
+        maximum_withdraw: maximum_withdraw.into(),
+        accrued_reward: output.format_capacity(maximum_withdraw.saturating_sub(capacity)),
+    })
+}
+
+/// The NervosDAO position of an address. The reward of the deposited cells is
+/// accrued until the tip block, the reward of the prepared cells is fixed by
+/// the prepare block.
+#[derive(Serialize)]
+pub struct DaoSummary {
+    pub deposited_cells: usize,
+    pub deposited_capacity: json_types::Capacity,
+    pub prepared_cells: usize,
+    pub prepared_capacity: json_types::Capacity,
+    pub reward: json_types::Capacity,
+}
+
+impl DaoSummary {
+    /// The deposited and prepared capacity
+    pub fn principal(&self) -> u64 {
+        self.deposited_capacity.value() + self.prepared_capacity.value()
+    }
+}
+
+pub fn dao_summary(rpc_url: &str, address: &Address) -> Result<DaoSummary, Error> {
+    let mut client = LightClientRpcClient::new(rpc_url);
+    let tip_header = HeaderView::from(client.get_tip_header()?);
+    let mut reward = 0;
+
+    let deposited = query_dao_cells(rpc_url, address, true, None)?;
+    for cell in &deposited {
+        let tx_hash: H256 = cell.out_point.tx_hash().unpack();
+        let deposit_header = HeaderView::from(get_any_transaction(&mut client, tx_hash)?.header);
+        reward += dao_reward(cell, &deposit_header, &tip_header)?;
+    }
+
+    // The prepared cell is at the same index of the deposit cell in the
+    // prepare transaction inputs
+    let prepared = query_dao_cells(rpc_url, address, false, None)?;
+    for cell in &prepared {
+        let tx_hash: H256 = cell.out_point.tx_hash().unpack();
+        let index: u32 = cell.out_point.index().unpack();
+        let tx_with_header = get_any_transaction(&mut client, tx_hash.clone())?;
+        let prepare_header = HeaderView::from(tx_with_header.header);
+        let prepare_tx = Transaction::from(tx_with_header.transaction.inner).into_view();
+        let deposit_tx_hash: H256 = prepare_tx
+            .inputs()
+            .get(index as usize)
+            .ok_or_else(|| anyhow!("deposit input not found: {:#x}-{}", tx_hash, index))?
+            .previous_output()
+            .tx_hash()
+            .unpack();
+        let deposit_header =
+            HeaderView::from(get_any_transaction(&mut client, deposit_tx_hash)?.header);
+        reward += dao_reward(cell, &deposit_header, &prepare_header)?;
+    }
+
+    let total_capacity = |cells: &[LiveCell]| {
+        cells
+            .iter()
+            .map(|cell| -> u64 { cell.output.capacity().unpack() })
+            .sum::<u64>()
+    };
+    Ok(DaoSummary {
+        deposited_cells: deposited.len(),
+        deposited_capacity: total_capacity(&deposited).into(),
+        prepared_cells: prepared.len(),
+        prepared_capacity: total_capacity(&prepared).into(),
+        reward: reward.into(),
+    })
+}
+
+/// The reward of the DAO cell if withdrawn at <withdraw_header>
+fn dao_reward(
+    cell: &LiveCell,
+    deposit_header: &HeaderView,
+    withdraw_header: &HeaderView,
+) -> Result<u64, Error> {
+    let capacity: u64 = cell.output.capacity().unpack();
+    let occupied_capacity = cell
+        .output
+        .occupied_capacity(Capacity::bytes(cell.output_data.len())?)?
+        .as_u64();
+    let maximum_withdraw = calculate_dao_maximum_withdraw4(
+        deposit_header,
+        withdraw_header,
+        &cell.output,
+        occupied_capacity,
+    );
+    Ok(maximum_withdraw.saturating_sub(capacity))
+}
+
+/// Wait for the prepare transaction committed, then wait for <wait_epochs>
+/// epochs or until all the prepared cells are withdrawable (when
+/// `wait_epochs` is None).
+fn wait_prepared(
+    rpc_url: &str,
+    prepare_tx_hash: &H256,
+    deposit_out_points: &[OutPoint],
+    wait_epochs: Option<u64>,
+) -> Result<(), Error> {
+    let mut client = LightClientRpcClient::new(rpc_url);
+    let prepare_header =
+        HeaderView::from(wait_tx_committed(&mut client, prepare_tx_hash, POLL_INTERVAL)?.header);
+    let target = if let Some(epochs) = wait_epochs {
+        let epoch = prepare_header.epoch();
+        EpochNumberWithFraction::new(epoch.number() + epochs, epoch.index(), epoch.length())
+    } else {
+        let mut target = EpochNumberWithFraction::new(0, 0, 1);
+        for out_point in deposit_out_points {
+            let deposit_tx_hash: H256 = out_point.tx_hash().unpack();
+            let deposit_header = client
+                .get_transaction(deposit_tx_hash.clone())?
+                .map(|tx_with_header| HeaderView::from(tx_with_header.header))
+                .ok_or_else(|| anyhow!("deposit transaction not found: {:#x}", deposit_tx_hash))?;
+            let unlock_point = minimal_unlock_point(&deposit_header, &prepare_header);
+            if unlock_point.to_rational() > target.to_rational() {
+                target = unlock_point;
+            }
+        }
+        target
+    };
+    let tip_header = wait_epoch(&mut client, target, POLL_INTERVAL)?;
+    println!(
+        "epoch {} reached (tip number: {})",
+        tip_header.epoch(),
+        tip_header.number()
+    );
+    Ok(())
+}
+
+/// Parse the out-points given by `--out-points` and `--out-points-json`
+fn parse_out_points(
+    out_points: Vec<String>,
+    out_points_json: Option<&Path>,
+) -> Result<Vec<OutPoint>, Error> {
+    let mut result = out_points
+        .iter()
+        .map(|input| parse_out_point(input))
+        .collect::<Result<Vec<_>, Error>>()?;
+    if let Some(path) = out_points_json {
+        let content = fs::read_to_string(path)?;
+        let json_out_points: Vec<json_types::OutPoint> = serde_json::from_str(&content)
+            .map_err(|err| anyhow!("parse out-points file {} error: {}", path.display(), err))?;
+        result.extend(json_out_points.into_iter().map(OutPoint::from));
+    }
+    if result.is_empty() {
+        return Err(anyhow!("missing out points"));
+    }
+    // The duplicated inputs make the transaction invalid
+    let mut seen = HashSet::new();
+    for out_point in &result {
+        if !seen.insert(out_point.clone()) {
+            let tx_hash: H256 = out_point.tx_hash().unpack();
+            let index: u32 = out_point.index().unpack();
+            return Err(anyhow!("duplicated out point: {:#x}-{}", tx_hash, index));
+        }
+    }
+    Ok(result)
 }
 
 // LiveCell index in a block
@@ -323,7 +998,8 @@ fn query_dao_cells(
     rpc_url: &str,
     address: &Address,
     is_deposit: bool,
-) -> Result<Vec<LiveCellInfo>, Error> {
+    max_cells: Option<usize>,
+) -> Result<Vec<LiveCell>, Error> {
     let dao_type_script = Script::new_builder()
         .code_hash(DAO_TYPE_HASH.pack())
         .hash_type(ScriptHashType::Type.into())
@@ -331,18 +1007,57 @@ fn query_dao_cells(
     let mut query = CellQueryOptions::new_lock(Script::from(address));
     query.secondary_script = Some(dao_type_script);
     query.data_len_range = Some(ValueRangeOption::new_exact(8));
-    query.min_total_capacity = u64::max_value();
+    query.min_total_capacity = u64::MAX;
 
-    let mut cell_collector = LightClientCellCollector::new(rpc_url);
-    let (cells, _) = cell_collector.collect_live_cells(&query, false)?;
     let cell_filter = if is_deposit {
         |block_number| block_number == 0
     } else {
         |block_number| block_number != 0
     };
-    Ok(cells
-        .iter()
-        .filter(|cell| cell_filter(LittleEndian::read_u64(&cell.output_data.as_ref()[0..8])))
-        .map(to_live_cell_info)
-        .collect::<Vec<_>>())
+    let is_dao_cell = |cell: &LiveCell| {
+        query.match_cell(cell, 0)
+            && cell_filter(LittleEndian::read_u64(&cell.output_data.as_ref()[0..8]))
+    };
+    let max_cells = match max_cells {
+        Some(max_cells) => max_cells,
+        None => {
+            let mut cell_collector = LightClientCellCollector::new(rpc_url);
+            let (cells, _) = cell_collector.collect_live_cells(&query, false)?;
+            return Ok(cells
+                .into_iter()
+                .filter(|cell| is_dao_cell(cell))
+                .collect::<Vec<_>>());
+        }
+    };
+
+    // Page the cells by ourselves, so that the collection can be stopped early
+    let mut client = LightClientRpcClient::new(rpc_url);
+    let search_key = SearchKey::from(query.clone());
+    let mut limit: u32 = 16;
+    let mut last_cursor = None;
+    let mut cells = Vec::new();
+    loop {
+        let page = client.get_cells(search_key.clone(), Order::Asc, limit.into(), last_cursor)?;
+        if page.objects.is_empty() {
+            return Ok(cells);
+        }
+        for cell in page.objects {
+            let live_cell = LiveCell::from(cell);
+            if !is_dao_cell(&live_cell) {
+                continue;
+            }
+            if cells.len() == max_cells {
+                eprintln!(
+                    "WARNING: the result is truncated, only the first {} cells are collected",
+                    max_cells
+                );
+                return Ok(cells);
+            }
+            cells.push(live_cell);
+        }
+        last_cursor = Some(page.last_cursor);
+        if limit < 4096 {
+            limit *= 2;
+        }
+    }
 }
@@ -1,11 +1,21 @@
+use std::collections::BTreeMap;
 use std::error::Error as StdErr;
+use std::process;
 
-use ckb_sdk::types::{Address, HumanCapacity};
-use clap::{ArgGroup, Parser, Subcommand};
+use ckb_sdk::types::Address;
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
 
+mod alias;
+mod archive;
+mod batch;
 mod common;
 mod dao;
+mod output;
+mod poll;
 mod rpc;
+mod scripts;
+mod throttle;
+mod util;
 mod wallet;
 
 #[derive(Parser, Debug)]
@@ -15,10 +25,45 @@ struct Cli {
     #[clap(long, value_name = "URL", default_value = "http://127.0.0.1:9000")]
     rpc: String,
 
-    /// Debug mode, print more information
+    /// Debug mode, print more information (and the full error chain)
     #[clap(long)]
     debug: bool,
 
+    /// Only print the top-level message of the error (default unless `--debug`)
+    #[clap(long)]
+    quiet_errors: bool,
+
+    /// Assume "yes" to the confirmations of read-only commands
+    #[clap(short = 'y', long)]
+    assume_yes_for_reads: bool,
+
+    /// Assume "yes" to the confirmations of the commands which send transactions
+    /// or change the light client state, be cautious to use this flag
+    #[clap(long)]
+    assume_yes_for_writes: bool,
+
+    /// Throttle all the RPC calls of the command (including the ones of building
+    /// the transaction) to at most <PER_SEC> calls per second
+    #[clap(long, value_name = "PER_SEC", value_parser = clap::value_parser!(u32).range(1..))]
+    rpc_rate_limit: Option<u32>,
+
+    /// Wait a few seconds for the address to be accepted by the light client
+    /// (registered by `rpc set-scripts` just now) instead of failing at once
+    #[clap(long)]
+    wait_script_accepted: bool,
+
+    /// Allow the addresses of different networks in one command (default rejected)
+    #[clap(long)]
+    allow_mixed_network: bool,
+
+    /// The network of the addresses displayed (mainnet, testnet or dev), default
+    /// is detected by the genesis hash of the node
+    #[clap(long, value_name = "NETWORK", value_parser = rpc::parse_network)]
+    network: Option<ckb_sdk::NetworkType>,
+
+    #[command(flatten)]
+    output: output::Output,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -31,30 +76,36 @@ enum Commands {
         #[arg(long, value_name = "ADDR")]
         address: Address,
     },
-    /// Transfer some capacity from given address to a receiver address
-    #[command(group(ArgGroup::new("from").required(true).args(["from_address", "from_key"])))]
-    Transfer {
-        /// The sender address (sighash only, also be used to match key in ckb-cli keystore)
+    /// Report the capacity, the NervosDAO position and the sUDT balances of the address
+    Portfolio {
+        /// The address
         #[arg(long, value_name = "ADDR")]
-        from_address: Option<Address>,
-
-        /// The sender private key (hex string, also be used to generate sighash address)
-        #[arg(long, value_name = "PRIVKEY")]
-        from_key: Option<common::HexH256>,
-
-        /// The receiver address
+        address: Address,
+    },
+    /// Report the block number and timestamp of the first and the last
+    /// transaction of the address
+    AddressActivity {
+        /// The address
         #[arg(long, value_name = "ADDR")]
-        to_address: Address,
-
-        /// The capacity to transfer (unit: CKB, example: 102.43)
-        #[arg(long, value_name = "CAPACITY")]
-        capacity: HumanCapacity,
-
-        /// Skip check <to-address> (default only allow sighash/multisig address), be cautious to use this flag
-        #[arg(long)]
-        skip_check_to_address: bool,
+        address: Address,
+    },
+    /// Unlock the keystore account(s) of the address to check the password,
+    /// nothing is signed
+    CheckPassword {
+        /// The sighash/multisig address
+        #[arg(long, value_name = "ADDR")]
+        from_address: Address,
+        /// The multisig config file (required when <from-address> is a multisig address)
+        #[arg(long, value_name = "FILE")]
+        multisig_config: Option<std::path::PathBuf>,
     },
+    /// Transfer some capacity from given address to a receiver address
+    Transfer(Box<wallet::TransferArgs>),
+    /// Sign the transfer bundle (exported by `transfer --export-bundle`) and send it
+    ImportBundle(wallet::ImportBundleArgs),
 
+    /// Monitor the capacity of addresses, alert when it drops below the threshold
+    Monitor(wallet::MonitorArgs),
     /// Nervos DAO operations
     #[command(subcommand)]
     Dao(dao::DaoCommands),
@@ -79,33 +130,259 @@ enum Commands {
     /// Send jsonrpc call the ckb-light-client rpc server
     #[command(subcommand)]
     Rpc(rpc::RpcCommands),
+
+    /// Utilities
+    #[command(subcommand)]
+    Util(util::UtilCommands),
+
+    /// Manage the address aliases, an address argument accepts `@NAME` of an alias
+    #[command(subcommand)]
+    Alias(alias::AliasCommands),
+
+    /// Print the version, the supported features, the commands and the RPC
+    /// methods may be called as JSON (for tools to detect the capabilities)
+    Capabilities,
+
+    /// Dump the full command tree (subcommands, arguments, descriptions) as JSON
+    #[command(hide = true)]
+    DumpHelpJson,
 }
 
-fn main() -> Result<(), Box<dyn StdErr>> {
-    let cli = Cli::parse();
+/// The optional features and whether they are supported by this build
+const FEATURES: &[(&str, bool)] = &[
+    ("sighash", true),
+    ("multisig", true),
+    ("nervos_dao", true),
+    ("sudt_balance", true),
+    ("sudt_transfer", false),
+    ("hardware_wallet", false),
+    ("offline_signing", true),
+    ("ckb_cli_tx", true),
+];
+
+/// The RPC methods the commands may call
+const RPC_METHODS: &[&str] = &[
+    "set_scripts",
+    "get_scripts",
+    "get_tip_header",
+    "get_genesis_block",
+    "get_header",
+    "get_transaction",
+    "fetch_header",
+    "fetch_transaction",
+    "get_cells",
+    "get_transactions",
+    "get_cells_capacity",
+    "send_transaction",
+    "get_peers",
+    "estimate_cycles",
+];
+
+impl Commands {
+    /// Whether the command sends transactions or changes the light client state
+    fn is_write(&self) -> bool {
+        match self {
+            Commands::Transfer(_) | Commands::ImportBundle(_) => true,
+            Commands::Dao(cmd) => cmd.is_write(),
+            Commands::Rpc(cmd) => cmd.is_write(),
+            Commands::GetCapacity { .. }
+            | Commands::Portfolio { .. }
+            | Commands::AddressActivity { .. }
+            | Commands::CheckPassword { .. }
+            | Commands::Monitor(_)
+            | Commands::ExampleSearchKey { .. }
+            | Commands::Util(_)
+            | Commands::Alias(_)
+            | Commands::Capabilities
+            | Commands::DumpHelpJson => false,
+        }
+    }
+
+    /// The addresses of the command which must be in the same network
+    fn addresses(&self) -> Vec<Address> {
+        match self {
+            Commands::Transfer(args) => args.addresses(),
+            Commands::Monitor(args) => args.addresses(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Check all the addresses are in the same network
+fn check_same_network(addresses: &[Address]) -> Result<(), anyhow::Error> {
+    if let Some(first) = addresses.first() {
+        if let Some(other) = addresses
+            .iter()
+            .find(|address| address.network() != first.network())
+        {
+            return Err(anyhow::anyhow!(
+                "the addresses are in different networks: {} ({:?}) and {} ({:?}), use `--allow-mixed-network` if it's intended",
+                first,
+                first.network(),
+                other,
+                other.network()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The full names (e.g. "dao deposit") of the visible leaf commands
+fn command_names(cmd: &clap::Command, prefix: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for sub_cmd in cmd.get_subcommands() {
+        if sub_cmd.is_hide_set() || sub_cmd.get_name() == "help" {
+            continue;
+        }
+        let name = format!("{}{}", prefix, sub_cmd.get_name());
+        if sub_cmd.has_subcommands() {
+            names.extend(command_names(sub_cmd, &format!("{} ", name)));
+        } else {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Serialize the command (and all the subcommands recursively) for the
+/// external tools, the hidden commands and arguments are skipped.
+fn command_to_json(cmd: &clap::Command) -> serde_json::Value {
+    let to_string = |value: Option<&clap::builder::StyledStr>| value.map(|s| s.to_string());
+    let args = cmd
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set() && arg.get_id() != "help" && arg.get_id() != "version")
+        .map(|arg| {
+            serde_json::json!({
+                "id": arg.get_id().as_str(),
+                "long": arg.get_long(),
+                "short": arg.get_short(),
+                "value_names": arg.get_value_names().map(|names| {
+                    names.iter().map(|name| name.as_str()).collect::<Vec<_>>()
+                }),
+                "help": to_string(arg.get_help()),
+                "long_help": to_string(arg.get_long_help()),
+                "required": arg.is_required_set(),
+                "takes_value": arg.get_num_args().map(|range| range.takes_values()).unwrap_or(false),
+                "multiple": arg.get_num_args().map(|range| range.max_values() > 1).unwrap_or(false)
+                    || matches!(arg.get_action(), clap::ArgAction::Append),
+                "default_values": arg
+                    .get_default_values()
+                    .iter()
+                    .map(|value| value.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>(),
+                "possible_values": arg
+                    .get_possible_values()
+                    .iter()
+                    .map(|value| value.get_name())
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let groups = cmd
+        .get_groups()
+        .filter(|group| group.get_args().next().is_some())
+        .map(|group| {
+            serde_json::json!({
+                "id": group.get_id().as_str(),
+                "args": group.get_args().map(|id| id.as_str()).collect::<Vec<_>>(),
+                "required": group.is_required_set(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let subcommands = cmd
+        .get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+        .map(command_to_json)
+        .collect::<Vec<_>>();
+    serde_json::json!({
+        "name": cmd.get_name(),
+        "about": to_string(cmd.get_about()),
+        "long_about": to_string(cmd.get_long_about()),
+        "args": args,
+        "groups": groups,
+        "subcommands": subcommands,
+    })
+}
+
+/// Print the error, with the "Caused by" chain unless <quiet>
+fn print_error(err: &dyn StdErr, quiet: bool) {
+    eprintln!("Error: {}", err);
+    if quiet {
+        return;
+    }
+    let mut source = err.source();
+    if source.is_some() {
+        eprintln!("\nCaused by:");
+    }
+    let mut index = 0;
+    while let Some(cause) = source {
+        eprintln!("    {}: {}", index, cause);
+        source = cause.source();
+        index += 1;
+    }
+}
+
+fn main() {
+    let args = match alias::resolve_args(std::env::args_os().collect()) {
+        Ok(args) => args,
+        Err(err) => {
+            print_error(err.as_ref(), true);
+            process::exit(1);
+        }
+    };
+    let cli = Cli::parse_from(args);
+    let quiet = cli.quiet_errors || !cli.debug;
+    if let Err(err) = run(cli) {
+        print_error(err.as_ref(), quiet);
+        process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn StdErr>> {
+    let output = cli.output;
+    let rpc_url = match cli.rpc_rate_limit {
+        Some(per_sec) => throttle::start_proxy(&cli.rpc, per_sec)?,
+        None => cli.rpc.clone(),
+    };
+    wallet::set_wait_script_accepted(cli.wait_script_accepted);
+    if let Some(network) = cli.network {
+        rpc::set_network(network);
+    }
+    if !cli.allow_mixed_network {
+        check_same_network(&cli.command.addresses())?;
+    }
+    common::set_assume_yes(if cli.command.is_write() {
+        cli.assume_yes_for_writes
+    } else {
+        cli.assume_yes_for_reads
+    });
     match cli.command {
         Commands::GetCapacity { address } => {
-            wallet::get_capacity(cli.rpc.as_str(), address)?;
+            wallet::get_capacity(rpc_url.as_str(), address, &output)?;
+        }
+        Commands::Portfolio { address } => {
+            wallet::portfolio(rpc_url.as_str(), address, &output)?;
+        }
+        Commands::AddressActivity { address } => {
+            wallet::address_activity(rpc_url.as_str(), address, &output)?;
         }
-        Commands::Transfer {
+        Commands::CheckPassword {
             from_address,
-            from_key,
-            to_address,
-            capacity,
-            skip_check_to_address,
+            multisig_config,
         } => {
-            wallet::transfer(
-                cli.rpc.as_str(),
-                from_address,
-                from_key.map(|v| v.0),
-                to_address,
-                capacity.0,
-                skip_check_to_address,
-                cli.debug,
-            )?;
+            wallet::check_password(from_address, multisig_config.as_deref())?;
+        }
+        Commands::Transfer(args) => {
+            wallet::transfer(rpc_url.as_str(), *args, &output, cli.debug)?;
+        }
+        Commands::ImportBundle(args) => {
+            wallet::import_bundle(rpc_url.as_str(), args, cli.debug)?;
+        }
+        Commands::Monitor(args) => {
+            wallet::monitor(rpc_url.as_str(), args, &output)?;
         }
         Commands::Dao(cmd) => {
-            dao::invoke(cli.rpc.as_str(), cmd, cli.debug)?;
+            dao::invoke(rpc_url.as_str(), cmd, &output, cli.debug)?;
         }
         Commands::ExampleSearchKey {
             with_filter,
@@ -118,10 +395,32 @@ fn main() -> Result<(), Box<dyn StdErr>> {
                 get_transactions,
                 get_cells,
                 get_cells_capacity,
-            );
+                &output,
+            )?;
         }
         Commands::Rpc(cmd) => {
-            rpc::invoke(cli.rpc.as_str(), cmd, cli.debug)?;
+            rpc::invoke(rpc_url.as_str(), cmd, &output, cli.debug)?;
+        }
+        Commands::Util(cmd) => {
+            util::invoke(rpc_url.as_str(), cmd, &output, cli.debug)?;
+        }
+        Commands::Alias(cmd) => {
+            alias::invoke(cmd)?;
+        }
+        Commands::Capabilities => {
+            let mut cmd = Cli::command();
+            cmd.build();
+            output.print_json(&serde_json::json!({
+                "version": env!("CARGO_PKG_VERSION"),
+                "features": FEATURES.iter().copied().collect::<BTreeMap<_, _>>(),
+                "commands": command_names(&cmd, ""),
+                "rpc_methods": RPC_METHODS,
+            }))?;
+        }
+        Commands::DumpHelpJson => {
+            let mut cmd = Cli::command();
+            cmd.build();
+            output.print_json(&command_to_json(&cmd))?;
         }
     }
     Ok(())
@@ -0,0 +1,328 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Error};
+use ckb_sdk::HumanCapacity;
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Where the results are emitted (stdout by default) and how the
+/// human-readable values are formatted
+#[derive(Args, Debug)]
+pub struct Output {
+    /// The format of the result printed to stdout
+    #[arg(long, value_enum, default_value = "json")]
+    output_format: OutputFormat,
+
+    /// Also write the JSON result to the file
+    #[arg(long, value_name = "FILE")]
+    output_json: Option<PathBuf>,
+
+    /// Write the JSON result to the unix domain socket instead of stdout
+    #[arg(long, value_name = "PATH")]
+    output_socket: Option<PathBuf>,
+
+    /// Round the capacity to <N> decimal places in human-readable output (JSON values are exact)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(0..=8))]
+    capacity_decimals: Option<u8>,
+
+    /// Annotate the human-readable capacity with the approximate USD value at the price (USD per CKB)
+    #[arg(long, value_name = "RATE", value_parser = parse_price)]
+    price_usd: Option<f64>,
+}
+
+fn parse_price(input: &str) -> Result<f64, String> {
+    match input.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate >= 0.0 => Ok(rate),
+        Ok(_) => Err("the price must be a non-negative number".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[derive(ValueEnum, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum OutputFormat {
+    Json,
+    /// Compact JSON with sorted keys, identical data always produces identical bytes
+    JsonCanonical,
+    Table,
+    /// Single line of `key=value` pairs (the nested fields are flattened as
+    /// `outer.inner=value`), one line for each item of a top-level array
+    Line,
+}
+
+impl Output {
+    /// Format the capacity (in shannons) as CKB, rounded to
+    /// `--capacity-decimals` decimal places if given.
+    pub fn format_capacity(&self, shannons: u64) -> String {
+        match self.capacity_decimals {
+            Some(decimals) if decimals < 8 => {
+                let unit = 10u64.pow(8 - decimals as u32);
+                let rounded = (shannons as u128 + unit as u128 / 2) / unit as u128;
+                if decimals == 0 {
+                    rounded.to_string()
+                } else {
+                    let scale = 10u128.pow(decimals as u32);
+                    format!(
+                        "{}.{:0width$}",
+                        rounded / scale,
+                        rounded % scale,
+                        width = decimals as usize
+                    )
+                }
+            }
+            _ => HumanCapacity(shannons).to_string(),
+        }
+    }
+
+    /// Format the capacity with the unit, followed by the approximate USD value
+    /// if `--price-usd` is given. Example: "100.0 CKB (~ $1.23)"
+    pub fn format_capacity_with_unit(&self, shannons: u64) -> String {
+        match self.usd_value(shannons) {
+            Some(usd) => format!("{} CKB (~ {})", self.format_capacity(shannons), usd),
+            None => format!("{} CKB", self.format_capacity(shannons)),
+        }
+    }
+
+    /// The approximate USD value of the capacity if `--price-usd` is given
+    pub fn usd_value(&self, shannons: u64) -> Option<String> {
+        self.price_usd
+            .map(|rate| format!("${:.2}", shannons as f64 / 100_000_000.0 * rate))
+    }
+
+    /// Emit the result to all the sinks, the value is serialized only once:
+    ///   * `--output-json`: the JSON result is written to the file
+    ///   * `--output-socket`: the JSON result is written to the unix domain
+    ///     socket (one JSON document followed by a newline) instead of stdout
+    ///   * stdout: printed in `--output-format`
+    ///
+    /// The JSON is canonical in all the sinks when `--output-format json-canonical`.
+    pub fn print_json<T: Serialize + ?Sized>(&self, value: &T) -> Result<(), Error> {
+        let value = serde_json::to_value(value)?;
+        let content = if self.output_format == OutputFormat::JsonCanonical {
+            to_canonical_string(&value)
+        } else {
+            serde_json::to_string_pretty(&value).unwrap()
+        };
+        if let Some(path) = self.output_json.as_ref() {
+            fs::write(path, &content)
+                .map_err(|err| anyhow!("write output json {} error: {}", path.display(), err))?;
+        }
+        if let Some(path) = self.output_socket.as_ref() {
+            let mut stream = connect(path).map_err(|err| {
+                anyhow!("connect output socket {} error: {}", path.display(), err)
+            })?;
+            stream.write_all(content.as_bytes())?;
+            stream.write_all(b"\n")?;
+            stream.flush()?;
+            return Ok(());
+        }
+        match self.output_format {
+            OutputFormat::Json | OutputFormat::JsonCanonical => println!("{}", content),
+            OutputFormat::Table => print!("{}", render_table(&value)),
+            OutputFormat::Line => print!("{}", render_lines(&value)),
+        }
+        Ok(())
+    }
+}
+
+/// Serialize the value as compact JSON with the object keys sorted (no matter
+/// how the map is ordered in memory).
+fn to_canonical_string(value: &Value) -> String {
+    match value {
+        Value::Array(items) => {
+            let items: Vec<_> = items.iter().map(to_canonical_string).collect();
+            format!("[{}]", items.join(","))
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            let entries: Vec<_> = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{}:{}",
+                        Value::String(key.clone()),
+                        to_canonical_string(value)
+                    )
+                })
+                .collect();
+            format!("{{{}}}", entries.join(","))
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Render the value as plain text tables:
+///   * array of objects: one row for each object, the columns are the keys
+///   * object: the scalar fields as key/value rows, the array fields as sub tables
+///   * others: the value itself
+fn render_table(value: &Value) -> String {
+    match value {
+        Value::Array(items) => render_rows(items),
+        Value::Object(map) => {
+            let mut rows = Vec::new();
+            let mut sub_tables = Vec::new();
+            for (key, value) in map {
+                match value {
+                    Value::Array(items) if items.iter().all(Value::is_object) => {
+                        sub_tables.push(format!("{}:\n{}", key, render_rows(items)));
+                    }
+                    _ => rows.push(vec![key.clone(), render_cell(value)]),
+                }
+            }
+            let mut output = format_rows(&[], &rows);
+            for sub_table in sub_tables {
+                output.push_str(&sub_table);
+            }
+            output
+        }
+        _ => format!("{}\n", render_cell(value)),
+    }
+}
+
+/// Render the value as `key=value` lines, see `OutputFormat::Line`
+fn render_lines(value: &Value) -> String {
+    let render_line = |value: &Value| {
+        let mut pairs = Vec::new();
+        flatten_pairs("", value, &mut pairs);
+        let line = pairs
+            .into_iter()
+            .map(|(key, value)| {
+                if key.is_empty() {
+                    value
+                } else {
+                    format!("{}={}", key, value)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("{}\n", line)
+    };
+    match value {
+        Value::Array(items) => items.iter().map(render_line).collect(),
+        _ => render_line(value),
+    }
+}
+
+fn flatten_pairs(prefix: &str, value: &Value, pairs: &mut Vec<(String, String)>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten_pairs(&join(key), value, pairs);
+            }
+        }
+        Value::Array(items) if items.iter().any(|item| item.is_object() || item.is_array()) => {
+            for (idx, item) in items.iter().enumerate() {
+                flatten_pairs(&join(&idx.to_string()), item, pairs);
+            }
+        }
+        Value::Array(items) => {
+            let items: Vec<_> = items.iter().map(render_cell).collect();
+            pairs.push((prefix.to_string(), quote_value(items.join(","))));
+        }
+        _ => pairs.push((prefix.to_string(), quote_value(render_cell(value)))),
+    }
+}
+
+/// Quote the value contains whitespace (or is empty), so the pairs can be split by spaces
+fn quote_value(value: String) -> String {
+    if value.is_empty() || value.contains(char::is_whitespace) || value.contains('"') {
+        Value::String(value).to_string()
+    } else {
+        value
+    }
+}
+
+fn render_rows(items: &[Value]) -> String {
+    let mut columns: Vec<String> = Vec::new();
+    for item in items {
+        if let Value::Object(map) = item {
+            for key in map.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+    }
+    if columns.is_empty() {
+        let rows: Vec<_> = items.iter().map(|item| vec![render_cell(item)]).collect();
+        return format_rows(&[], &rows);
+    }
+    let rows: Vec<_> = items
+        .iter()
+        .map(|item| {
+            columns
+                .iter()
+                .map(|column| render_cell(item.get(column).unwrap_or(&Value::Null)))
+                .collect()
+        })
+        .collect();
+    format_rows(&columns, &rows)
+}
+
+fn render_cell(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Bool(_) | Value::Number(_) => value.to_string(),
+        Value::Array(_) | Value::Object(_) => value.to_string(),
+    }
+}
+
+/// Align the columns (the header is omitted when empty)
+fn format_rows(header: &[String], rows: &[Vec<String>]) -> String {
+    let column_count = rows
+        .iter()
+        .map(Vec::len)
+        .chain(Some(header.len()))
+        .max()
+        .unwrap_or(0);
+    let mut widths = vec![0; column_count];
+    for row in Some(header)
+        .into_iter()
+        .chain(rows.iter().map(Vec::as_slice))
+    {
+        for (idx, cell) in row.iter().enumerate() {
+            widths[idx] = widths[idx].max(cell.chars().count());
+        }
+    }
+    let format_row = |row: &[String]| {
+        let line = row
+            .iter()
+            .enumerate()
+            .map(|(idx, cell)| format!("{:width$}", cell, width = widths[idx]))
+            .collect::<Vec<_>>()
+            .join("  ");
+        format!("{}\n", line.trim_end())
+    };
+    let mut output = String::new();
+    if !header.is_empty() {
+        output.push_str(&format_row(header));
+    }
+    for row in rows {
+        output.push_str(&format_row(row));
+    }
+    output
+}
+
+#[cfg(unix)]
+fn connect(path: &std::path::Path) -> std::io::Result<std::os::unix::net::UnixStream> {
+    std::os::unix::net::UnixStream::connect(path)
+}
+
+#[cfg(not(unix))]
+fn connect(_path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "unix domain socket is not supported on this platform",
+    ))
+}
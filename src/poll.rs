@@ -0,0 +1,45 @@
+//! Poll the light client until the expected state is reached.
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Error;
+use ckb_sdk::rpc::{ckb_light_client::TransactionWithHeader, LightClientRpcClient};
+use ckb_types::{
+    core::{EpochNumberWithFraction, HeaderView},
+    H256,
+};
+
+/// The default interval between two polls
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Wait until the transaction is committed (and synchronized by the light
+/// client), the transaction must be related to the registered scripts.
+pub fn wait_tx_committed(
+    client: &mut LightClientRpcClient,
+    tx_hash: &H256,
+    interval: Duration,
+) -> Result<TransactionWithHeader, Error> {
+    println!("waiting for transaction {:#x} to be committed...", tx_hash);
+    loop {
+        if let Some(tx_with_header) = client.get_transaction(tx_hash.clone())? {
+            return Ok(tx_with_header);
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// Wait until the epoch of tip header reaches `target`, return the tip header.
+pub fn wait_epoch(
+    client: &mut LightClientRpcClient,
+    target: EpochNumberWithFraction,
+    interval: Duration,
+) -> Result<HeaderView, Error> {
+    println!("waiting for epoch {}...", target);
+    loop {
+        let tip_header = HeaderView::from(client.get_tip_header()?);
+        if tip_header.epoch().to_rational() >= target.to_rational() {
+            return Ok(tip_header);
+        }
+        thread::sleep(interval);
+    }
+}
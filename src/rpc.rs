@@ -1,20 +1,37 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Error};
 use ckb_jsonrpc_types as json_types;
 use ckb_sdk::{
     rpc::ckb_light_client::{
-        LightClientRpcClient, Order as JsonOrder, ScriptStatus, ScriptType, SearchKey,
-        SearchKeyFilter,
+        Cell, LightClientRpcClient, Order as JsonOrder, Pagination, ScriptStatus, ScriptType,
+        SearchKey, SearchKeyFilter,
     },
-    Address,
+    traits::LightClientTransactionDependencyProvider,
+    Address, AddressPayload, NetworkType,
 };
-use ckb_types::{h256, packed::Script};
-use clap::{Subcommand, ValueEnum};
+use ckb_types::{
+    core::{BlockView, TransactionView},
+    h256,
+    packed::{self, Script},
+    prelude::*,
+    H256,
+};
+use clap::{ArgGroup, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
+use crate::archive;
+use crate::batch::{batch_call, call};
 use crate::common::{remove0x, HexH256};
+use crate::output::Output;
+use crate::scripts::ScriptName;
+use crate::util::CkbCliTx;
 
 #[derive(Subcommand, Debug)]
 pub enum RpcCommands {
@@ -24,7 +41,7 @@ pub enum RpcCommands {
         #[arg(
             long,
             value_name = "FILE|ADDR-INT",
-            long_help = "The script status list.\n\nThe argument format can be a string for lock script or a JSON file for any script type.\nThe string format: \"ADDR,NUM\", example: \"ckt1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqgaqanf,5896000\".\nThe file data format (json):\n{\n  \"script\": {\n    \"code_hash\": \"0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8\",\n    \"hash_type\": \"type\",\n    \"args\": \"0x0000000000000000000000000000000000000000\"\n  },\n  \"script_type\": \"lock\",\n  \"block_number\": \"0xbb64\"\n}"
+            long_help = "The script status list.\n\nThe argument format can be a string for lock script or a JSON file for any script type.\nThe string format: \"ADDR,NUM[,lock|type]\" (the script type is lock by default), example: \"ckt1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqgaqanf,5896000\".\nThe ADDR can also be a script in \"CODE_HASH:HASH_TYPE:ARGS\" format (the hash type is data, type or data1).\nThe file data format (json):\n{\n  \"script\": {\n    \"code_hash\": \"0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8\",\n    \"hash_type\": \"type\",\n    \"args\": \"0x0000000000000000000000000000000000000000\"\n  },\n  \"script_type\": \"lock\",\n  \"block_number\": \"0xbb64\"\n}"
         )]
         scripts: Vec<String>,
 
@@ -32,6 +49,28 @@ pub enum RpcCommands {
         /// accept empty script status list.
         #[arg(long)]
         allow_empty: bool,
+
+        /// Print the difference between the current and the given script status
+        /// list, the list is not applied unless `--apply` is given
+        #[arg(long)]
+        diff: bool,
+
+        /// Apply the script status list after printing the difference
+        #[arg(long, requires = "diff")]
+        apply: bool,
+    },
+    /// Set the script status list from a CSV file, each row is
+    /// `address,block_number[,script_type]` (the script type is lock by default),
+    /// the address can also be a script in `code_hash:hash_type:args` format
+    SetScriptsFromCsv {
+        /// The CSV file (the header row, blank lines and lines start with '#' are skipped)
+        #[arg(long, value_name = "FILE")]
+        file: PathBuf,
+
+        /// Append to (instead of replace) the current script status list, the
+        /// existing entry of the same script is updated
+        #[arg(long)]
+        append: bool,
     },
     GetScripts,
     GetCells {
@@ -44,6 +83,22 @@ pub enum RpcCommands {
         limit: u32,
         #[arg(long, value_name = "HEX")]
         after: Option<String>,
+        /// Fetch all the pages (start from <after>), the <limit> is used as page size
+        #[arg(long)]
+        all: bool,
+        /// Only output cells whose containing block is at least <N> blocks
+        /// deep (the tip block counts as 1 confirmation)
+        #[arg(long, value_name = "N")]
+        min_confirmations: Option<u64>,
+        /// Only output cells whose type script hash is any of the given hashes
+        /// (filtered on the client side, after the page is fetched, so a page may
+        /// contain less than <limit> cells)
+        #[arg(long, value_name = "H256")]
+        type_hash: Vec<HexH256>,
+        /// Only output cells whose type script is any of the well-known scripts
+        /// (of the network of the node, any args), filtered like <type-hash>
+        #[arg(long, value_enum, value_name = "NAME")]
+        type_name: Vec<ScriptName>,
     },
     GetTransactions {
         /// The search key config, use `example-search-key` sub-command to generate a example value
@@ -55,15 +110,62 @@ pub enum RpcCommands {
         limit: u32,
         #[arg(long, value_name = "HEX")]
         after: Option<String>,
+        /// Fetch all the pages (start from <after>), the <limit> is used as page size
+        #[arg(long)]
+        all: bool,
+    },
+    /// Poll the new transactions (grouped by transaction) of the address, or of
+    /// all the registered scripts, and print them as they are synchronized, until
+    /// interrupted
+    StreamTransactions {
+        /// Only stream the transactions of the lock script of the address
+        #[arg(long, value_name = "ADDR")]
+        address: Option<Address>,
+        /// Stream from the block (default is the block after the current tip)
+        #[arg(long, value_name = "NUM")]
+        from_block: Option<u64>,
+        /// The page size of each `get_transactions` call
+        #[arg(long, value_name = "NUM", default_value = "100")]
+        limit: u32,
+        /// The interval between two polls (unit: second)
+        #[arg(long, value_name = "SECS", default_value = "5")]
+        interval: u64,
+        /// Save the last cursor of each script to the file after each poll, and
+        /// resume from it on restart (`--from-block` is ignored if it exists)
+        #[arg(long, value_name = "FILE")]
+        since_file: Option<PathBuf>,
     },
     GetCellsCapacity {
         /// The search key config, use `example-search-key` sub-command to generate a example value
         #[arg(long, value_name = "FILE")]
         search_key: PathBuf,
     },
+    #[command(group(ArgGroup::new("input").required(true).args(["transaction", "archive"])))]
     SendTransaction {
+        #[arg(long, value_name = "FILE")]
+        transaction: Option<PathBuf>,
+
+        /// Send all the transaction JSON files (`*.json`) in the archive (.zip,
+        /// .tar or .tar.gz) in file name order
+        #[arg(long, value_name = "FILE")]
+        archive: Option<PathBuf>,
+    },
+    /// Send the transaction file of ckb-cli: the `ckb-cli tx` file (the witness
+    /// locks are filled with the signatures) or the `ckb-cli mock-tx` file
+    SendCkbCliTx {
+        #[arg(long, value_name = "FILE")]
+        file: PathBuf,
+    },
+    /// Verify the transaction by the node without broadcasting it (the
+    /// `estimate_cycles` RPC), report the cycles consumed or the script error
+    TestTransaction {
         #[arg(long, value_name = "FILE")]
         transaction: PathBuf,
+
+        /// The RPC url of the node to verify the transaction (e.g. a full node),
+        /// default is the light client
+        #[arg(long, value_name = "URL")]
+        node_rpc: Option<String>,
     },
     GetTipHeader,
     GetGenesisBlock,
@@ -90,6 +192,169 @@ pub enum RpcCommands {
         tx_hash: HexH256,
     },
     GetPeers,
+    /// Verify the genesis block hash of the light client matches the expected network
+    VerifyNetwork {
+        #[arg(long, value_enum)]
+        network: Network,
+    },
+    /// Estimate how long the synchronization from the block will take, the rate
+    /// is measured by sampling the progress of the registered scripts which are
+    /// behind the tip
+    EstimateSync {
+        /// The start block number of the new script
+        #[arg(long, value_name = "N")]
+        from_block: u64,
+
+        /// How long to sample the synchronization progress (unit: second)
+        #[arg(long, value_name = "SECS", default_value = "10")]
+        sample_secs: u64,
+    },
+    /// Report the synchronized block number of each registered script (compared to the tip)
+    SyncedRange {
+        /// Only report the lock script of the address
+        #[arg(long, value_name = "ADDR")]
+        address: Option<Address>,
+    },
+}
+
+#[derive(Serialize)]
+struct SyncEstimate {
+    from_block: json_types::BlockNumber,
+    tip_number: json_types::BlockNumber,
+    /// The number of blocks to synchronize
+    blocks: u64,
+    /// The measured synchronization rate (blocks per second)
+    sync_rate: Option<f64>,
+    /// The estimated time to synchronize all the blocks (unit: second)
+    estimated_seconds: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ScriptSyncedRange {
+    script: json_types::Script,
+    script_type: ScriptType,
+    /// The address of the lock script
+    address: Option<String>,
+    /// The block filters are checked up to this block number (the scripts
+    /// status only records this number, the starting block number is lost)
+    synced_number: json_types::BlockNumber,
+    tip_number: json_types::BlockNumber,
+    /// How many blocks the synchronization is behind the tip
+    behind: json_types::BlockNumber,
+}
+
+#[derive(ValueEnum, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+}
+
+impl Network {
+    pub fn from_genesis_hash(genesis_hash: &H256) -> Option<Network> {
+        [Network::Mainnet, Network::Testnet]
+            .into_iter()
+            .find(|network| &network.genesis_hash() == genesis_hash)
+    }
+
+    pub fn genesis_hash(self) -> H256 {
+        match self {
+            Network::Mainnet => {
+                h256!("0x92b197aa1fba0f63633922c61c92375c9c074a93e85963554f5499fe1450d0e5")
+            }
+            Network::Testnet => {
+                h256!("0x10639e0895502b5688a6be8cf69460d76541bfa4821629d86d62ba0aae3f9606")
+            }
+        }
+    }
+}
+
+/// The network of the node (used to display the addresses), set by
+/// `--network` or detected once by the genesis hash.
+static NETWORK: Mutex<Option<NetworkType>> = Mutex::new(None);
+
+/// Override the network detected by the genesis hash
+pub fn set_network(network: NetworkType) {
+    *NETWORK.lock().unwrap() = Some(network);
+}
+
+/// Parse the `--network` value: mainnet, testnet or dev
+pub fn parse_network(input: &str) -> Result<NetworkType, String> {
+    match input {
+        "mainnet" => Ok(NetworkType::Mainnet),
+        "testnet" => Ok(NetworkType::Testnet),
+        "dev" => Ok(NetworkType::Dev),
+        _ => Err(format!(
+            "invalid network: {}, expected mainnet, testnet or dev",
+            input
+        )),
+    }
+}
+
+/// The page size of `get_cells`/`get_transactions` above it is warned (not a
+/// limit of the light client RPC), a large page may be slow to return
+const LARGE_PAGE_LIMIT: u32 = 10000;
+
+/// Warn the large page size, the limit is passed to the node as given
+fn check_limit(limit: u32) -> u32 {
+    if limit > LARGE_PAGE_LIMIT {
+        eprintln!(
+            "WARNING: `--limit` {} is larger than {}, the request may be slow",
+            limit, LARGE_PAGE_LIMIT
+        );
+    }
+    limit
+}
+
+/// A full page means there may be more objects
+fn hint_full_page(count: usize, limit: u32, last_cursor: &json_types::JsonBytes) {
+    if count > 0 && count == limit as usize {
+        eprintln!(
+            "NOTE: the page is full, there may be more results, use `--after 0x{}` for the next page or `--all` to fetch all pages",
+            hex::encode(last_cursor.as_bytes())
+        );
+    }
+}
+
+/// The progress of `stream-transactions` saved in the since file
+#[derive(Serialize, Deserialize)]
+struct StreamState {
+    from_block: json_types::BlockNumber,
+    cursors: Vec<StreamCursor>,
+}
+
+/// The last cursor (None if no transaction found yet) of the search key
+#[derive(Serialize, Deserialize)]
+struct StreamCursor {
+    search_key: SearchKey,
+    cursor: Option<json_types::JsonBytes>,
+}
+
+/// Detect the network type by the genesis hash of the light client, unknown
+/// genesis is treated as a dev chain.
+pub fn get_network_type(client: &mut LightClientRpcClient) -> Result<NetworkType, Error> {
+    if let Some(network) = *NETWORK.lock().unwrap() {
+        return Ok(network);
+    }
+    Ok(network_type_of_genesis(client.get_genesis_block()?))
+}
+
+fn network_type_of_genesis(genesis_block: json_types::BlockView) -> NetworkType {
+    let mut network = NETWORK.lock().unwrap();
+    if let Some(network) = *network {
+        return network;
+    }
+    let genesis_hash: H256 = BlockView::from(genesis_block).hash().unpack();
+    let detected = match Network::from_genesis_hash(&genesis_hash) {
+        Some(Network::Mainnet) => NetworkType::Mainnet,
+        Some(Network::Testnet) => NetworkType::Testnet,
+        None => NetworkType::Dev,
+    };
+    eprintln!(
+        "network: {:?} (detected by the genesis hash {:#x}, use `--network` to override)",
+        detected, genesis_hash
+    );
+    *network = Some(detected);
+    detected
 }
 
 #[derive(ValueEnum, Eq, PartialEq, Clone, Copy, Debug)]
@@ -107,12 +372,26 @@ impl From<Order> for JsonOrder {
     }
 }
 
-pub fn invoke(rpc_url: &str, cmd: RpcCommands, debug: bool) -> Result<(), Error> {
+impl RpcCommands {
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            RpcCommands::SetScripts { .. }
+                | RpcCommands::SetScriptsFromCsv { .. }
+                | RpcCommands::SendTransaction { .. }
+                | RpcCommands::SendCkbCliTx { .. }
+        )
+    }
+}
+
+pub fn invoke(rpc_url: &str, cmd: RpcCommands, output: &Output, debug: bool) -> Result<(), Error> {
     let mut client = LightClientRpcClient::new(rpc_url);
     match cmd {
         RpcCommands::SetScripts {
             scripts,
             allow_empty,
+            diff,
+            apply,
         } => {
             if scripts.is_empty() && !allow_empty {
                 return Err(anyhow!(
@@ -136,18 +415,66 @@ pub fn invoke(rpc_url: &str, cmd: RpcCommands, debug: bool) -> Result<(), Error>
                     serde_json::to_string_pretty(&scripts).unwrap()
                 );
             }
+            if diff {
+                let changes = print_scripts_diff(&client.get_scripts()?, &scripts);
+                if !apply {
+                    println!("not applied, use `--apply` to apply the changes");
+                    return Ok(());
+                }
+                if changes == 0 {
+                    return Ok(());
+                }
+            }
             client.set_scripts(scripts)?;
             println!("success!");
         }
+        RpcCommands::SetScriptsFromCsv { file, append } => {
+            let content = fs::read_to_string(&file)?;
+            let mut scripts = Vec::new();
+            for (idx, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty()
+                    || line.starts_with('#')
+                    || (idx == 0 && line.starts_with("address"))
+                {
+                    continue;
+                }
+                let status = parse_addr_script(line)
+                    .map_err(|err| anyhow!("{} line {}: {}", file.display(), idx + 1, err))?;
+                scripts.push(status);
+            }
+            if scripts.is_empty() {
+                return Err(anyhow!("no script status found in {}", file.display()));
+            }
+            let total = scripts.len();
+            if append {
+                let mut current = client.get_scripts()?;
+                current.retain(|old| !scripts.iter().any(|new| is_same_script(old, new)));
+                current.extend(scripts);
+                scripts = current;
+            }
+            if debug {
+                println!(
+                    "scripts: \n{}",
+                    serde_json::to_string_pretty(&scripts).unwrap()
+                );
+            }
+            client.set_scripts(scripts)?;
+            println!("success! {} scripts registered", total);
+        }
         RpcCommands::GetScripts => {
             let scripts = client.get_scripts()?;
-            println!("{}", serde_json::to_string_pretty(&scripts).unwrap());
+            output.print_json(&scripts)?;
         }
         RpcCommands::GetCells {
             search_key,
             order,
             limit,
             after,
+            all,
+            min_confirmations,
+            type_hash,
+            type_name,
         } => {
             let content = fs::read_to_string(&search_key)?;
             let search_key: SearchKey = serde_json::from_str(&content)?;
@@ -157,93 +484,628 @@ pub fn invoke(rpc_url: &str, cmd: RpcCommands, debug: bool) -> Result<(), Error>
                 .map(|s| hex::decode(s).map(json_types::JsonBytes::from_vec))
                 .transpose()
                 .map_err(|err| anyhow!("parse `after` field error: {}", err))?;
-            let page = client.get_cells(search_key, order.into(), limit.into(), after)?;
-            println!("{}", serde_json::to_string_pretty(&page).unwrap());
+            // Fetch the tip first, so that cells committed after it are
+            // treated as unconfirmed.
+            let min_confirmed = match min_confirmations {
+                Some(n) => {
+                    let tip_number = client.get_tip_header()?.inner.number.value();
+                    Some((tip_number + 1).checked_sub(n))
+                }
+                None => None,
+            };
+            let is_confirmed = |cell: &Cell| match min_confirmed {
+                Some(Some(max_block_number)) => cell.block_number.value() <= max_block_number,
+                Some(None) => false,
+                None => true,
+            };
+            let type_hashes: Vec<H256> = type_hash.into_iter().map(|hash| hash.0).collect();
+            let network = if type_name.is_empty() {
+                NetworkType::Dev
+            } else {
+                get_network_type(&mut client)?
+            };
+            for name in &type_name {
+                if name.code_hash(network).is_none() {
+                    return Err(anyhow!(
+                        "the code hash of {:?} is unknown in {:?} network",
+                        name,
+                        network
+                    ));
+                }
+            }
+            let is_type_matched = |cell: &Cell| {
+                (type_hashes.is_empty() && type_name.is_empty())
+                    || cell.output.type_.as_ref().is_some_and(|script| {
+                        let script = Script::from(script.clone());
+                        let hash: H256 = script.calc_script_hash().unpack();
+                        type_hashes.contains(&hash)
+                            || type_name
+                                .iter()
+                                .any(|name| name.is_instance(network, &script))
+                    })
+            };
+            let keep = |cell: &Cell| is_confirmed(cell) && is_type_matched(cell);
+            if all {
+                fetch_all_pages(
+                    output,
+                    limit,
+                    after,
+                    |limit, after| {
+                        Ok(client.get_cells(
+                            search_key.clone(),
+                            order.into(),
+                            limit.into(),
+                            after,
+                        )?)
+                    },
+                    keep,
+                )?;
+            } else {
+                let limit = check_limit(limit);
+                let mut page = client.get_cells(search_key, order.into(), limit.into(), after)?;
+                hint_full_page(page.objects.len(), limit, &page.last_cursor);
+                page.objects.retain(keep);
+                output.print_json(&page)?;
+            }
         }
         RpcCommands::GetTransactions {
             search_key,
             order,
             limit,
             after,
+            all,
         } => {
             let content = fs::read_to_string(&search_key)?;
             let search_key: SearchKey = serde_json::from_str(&content)?;
             let after = after
                 .as_ref()
                 .map(|s| remove0x(s))
-                .map(|s| hex::decode(&s).map(json_types::JsonBytes::from_vec))
+                .map(|s| hex::decode(s).map(json_types::JsonBytes::from_vec))
                 .transpose()
                 .map_err(|err| anyhow!("parse `after` field error: {}", err))?;
-            let page = client.get_transactions(search_key, order.into(), limit.into(), after)?;
-            println!("{}", serde_json::to_string_pretty(&page).unwrap());
+            if all {
+                fetch_all_pages(
+                    output,
+                    limit,
+                    after,
+                    |limit, after| {
+                        Ok(client.get_transactions(
+                            search_key.clone(),
+                            order.into(),
+                            limit.into(),
+                            after,
+                        )?)
+                    },
+                    |_| true,
+                )?;
+            } else {
+                let limit = check_limit(limit);
+                let page =
+                    client.get_transactions(search_key, order.into(), limit.into(), after)?;
+                hint_full_page(page.objects.len(), limit, &page.last_cursor);
+                output.print_json(&page)?;
+            }
+        }
+        RpcCommands::StreamTransactions {
+            address,
+            from_block,
+            limit,
+            interval,
+            since_file,
+        } => {
+            if limit == 0 {
+                return Err(anyhow!("`--limit` must be greater than 0"));
+            }
+            let limit = check_limit(limit);
+            let mut state = match since_file.as_ref().filter(|path| path.exists()) {
+                Some(path) => {
+                    let content = fs::read_to_string(path)?;
+                    let state: StreamState = serde_json::from_str(&content).map_err(|err| {
+                        anyhow!("parse since file {} error: {}", path.display(), err)
+                    })?;
+                    println!("resume the stream from {}", path.display());
+                    state
+                }
+                None => StreamState {
+                    from_block: match from_block {
+                        Some(number) => number,
+                        None => client.get_tip_header()?.inner.number.value() + 1,
+                    }
+                    .into(),
+                    cursors: Vec::new(),
+                },
+            };
+            let from_block = state.from_block.value();
+            println!("streaming the transactions from block {}...", from_block);
+            loop {
+                let scripts = match address.as_ref() {
+                    Some(address) => vec![(Script::from(address).into(), ScriptType::Lock)],
+                    None => client
+                        .get_scripts()?
+                        .into_iter()
+                        .map(|status| (status.script, status.script_type))
+                        .collect(),
+                };
+                for (script, script_type) in scripts {
+                    let is_same_key = |search_key: &SearchKey| {
+                        search_key.script == script
+                            && matches!(
+                                (&search_key.script_type, &script_type),
+                                (ScriptType::Lock, ScriptType::Lock)
+                                    | (ScriptType::Type, ScriptType::Type)
+                            )
+                    };
+                    let cursors = &mut state.cursors;
+                    let idx = match cursors
+                        .iter()
+                        .position(|item| is_same_key(&item.search_key))
+                    {
+                        Some(idx) => idx,
+                        None => {
+                            let search_key = SearchKey {
+                                script,
+                                script_type,
+                                filter: Some(SearchKeyFilter {
+                                    block_range: Some([from_block.into(), u64::MAX.into()]),
+                                    ..Default::default()
+                                }),
+                                with_data: None,
+                                group_by_transaction: Some(true),
+                            };
+                            cursors.push(StreamCursor {
+                                search_key,
+                                cursor: None,
+                            });
+                            cursors.len() - 1
+                        }
+                    };
+                    let StreamCursor { search_key, cursor } = &mut cursors[idx];
+                    loop {
+                        let page = client.get_transactions(
+                            search_key.clone(),
+                            JsonOrder::Asc,
+                            limit.into(),
+                            cursor.clone(),
+                        )?;
+                        let count = page.objects.len();
+                        for tx in page.objects {
+                            output.print_json(&tx)?;
+                        }
+                        if count == 0 {
+                            break;
+                        }
+                        *cursor = Some(page.last_cursor);
+                        if count < limit as usize {
+                            break;
+                        }
+                    }
+                }
+                if let Some(path) = since_file.as_ref() {
+                    // Write to a temporary file then rename, so that the file is
+                    // never left half written
+                    let tmp_path = path.with_extension("tmp");
+                    fs::write(&tmp_path, serde_json::to_string_pretty(&state).unwrap())?;
+                    fs::rename(&tmp_path, path)?;
+                }
+                thread::sleep(Duration::from_secs(interval));
+            }
         }
         RpcCommands::GetCellsCapacity { search_key } => {
             let content = fs::read_to_string(&search_key)?;
             let search_key: SearchKey = serde_json::from_str(&content)?;
             let cells_capacity = client.get_cells_capacity(search_key)?;
-            println!("{}", serde_json::to_string_pretty(&cells_capacity).unwrap());
+            output.print_json(&cells_capacity)?;
+        }
+        RpcCommands::SendTransaction {
+            transaction,
+            archive,
+        } => {
+            if let Some(path) = archive {
+                let files: Vec<_> = archive::read_files(&path)?
+                    .into_iter()
+                    .filter(|(name, _)| name.to_lowercase().ends_with(".json"))
+                    .collect();
+                if files.is_empty() {
+                    return Err(anyhow!("no transaction file in {}", path.display()));
+                }
+                // Parse all the files first, so that nothing is sent if any is invalid
+                let txs = files
+                    .into_iter()
+                    .map(|(name, content)| {
+                        serde_json::from_slice::<json_types::Transaction>(&content)
+                            .map(|tx| (name.clone(), tx))
+                            .map_err(|err| anyhow!("invalid transaction file {}: {}", name, err))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                for (name, tx) in txs {
+                    let tx_hash = client
+                        .send_transaction(tx)
+                        .map_err(|err| anyhow!("send transaction {} error: {}", name, err))?;
+                    println!("Transaction sent!, file: {}, hash: {:#x}", name, tx_hash);
+                }
+            } else {
+                let content = fs::read_to_string(transaction.expect("transaction"))?;
+                let tx: json_types::Transaction = serde_json::from_str(&content)?;
+                let tx_hash = client.send_transaction(tx)?;
+                println!("Transaction sent!, hash: {:#x}", tx_hash);
+            }
+        }
+        RpcCommands::SendCkbCliTx { file } => {
+            let tx = load_ckb_cli_tx(rpc_url, &file)?;
+            if debug {
+                println!(
+                    "tx: {}",
+                    serde_json::to_string_pretty(&json_types::TransactionView::from(tx.clone()))
+                        .unwrap()
+                );
+            }
+            let tx_hash = client.send_transaction(tx.data().into())?;
+            println!("Transaction sent!, hash: {:#x}", tx_hash);
         }
-        RpcCommands::SendTransaction { transaction } => {
+        RpcCommands::TestTransaction {
+            transaction,
+            node_rpc,
+        } => {
             let content = fs::read_to_string(&transaction)?;
             let tx: json_types::Transaction = serde_json::from_str(&content)?;
-            let tx_hash = client.send_transaction(tx)?;
-            println!("Transaction sent!, hash: {:#x}", tx_hash);
+            let tx_hash: H256 = packed::Transaction::from(tx.clone())
+                .calc_tx_hash()
+                .unpack();
+            let node_rpc = node_rpc.as_deref().unwrap_or(rpc_url);
+            let result = call(node_rpc, "estimate_cycles", json!([tx])).map_err(|err| {
+                anyhow!("transaction {:#x} verification failed: {}", tx_hash, err)
+            })?;
+            let cycles: json_types::Cycle = serde_json::from_value(result["cycles"].clone())?;
+            output.print_json(&json!({
+                "tx_hash": tx_hash,
+                "cycles": cycles,
+                "cycles_decimal": cycles.value(),
+            }))?;
         }
         RpcCommands::GetTipHeader => {
             let header = client.get_tip_header()?;
-            println!("{}", serde_json::to_string_pretty(&header).unwrap());
+            output.print_json(&header)?;
         }
         RpcCommands::GetGenesisBlock => {
             let block = client.get_genesis_block()?;
-            println!("{}", serde_json::to_string_pretty(&block).unwrap());
+            output.print_json(&block)?;
         }
         RpcCommands::GetHeader { block_hash } => {
             let value = client.get_header(block_hash.0)?;
-            println!("{}", serde_json::to_string_pretty(&value).unwrap());
+            output.print_json(&value)?;
         }
         RpcCommands::GetTransaction { tx_hash } => {
             let value = client.get_transaction(tx_hash.0)?;
-            println!("{}", serde_json::to_string_pretty(&value).unwrap());
+            output.print_json(&value)?;
         }
         RpcCommands::FetchHeader { block_hash } => {
             let value = client.fetch_header(block_hash.0)?;
-            println!("{}", serde_json::to_string_pretty(&value).unwrap());
+            output.print_json(&value)?;
         }
         RpcCommands::FetchTransaction { tx_hash } => {
             let value = client.fetch_transaction(tx_hash.0)?;
-            println!("{}", serde_json::to_string_pretty(&value).unwrap());
+            output.print_json(&value)?;
         }
         RpcCommands::GetPeers => {
             let peers = client.get_peers()?;
-            println!("{}", serde_json::to_string_pretty(&peers).unwrap());
+            output.print_json(&peers)?;
+        }
+        RpcCommands::VerifyNetwork { network } => {
+            let genesis_block = BlockView::from(client.get_genesis_block()?);
+            let genesis_hash: H256 = genesis_block.hash().unpack();
+            let expected_hash = network.genesis_hash();
+            if genesis_hash != expected_hash {
+                return Err(anyhow!(
+                    "genesis hash not match, expected {:?} genesis hash: {:#x}, got: {:#x}",
+                    network,
+                    expected_hash,
+                    genesis_hash
+                ));
+            }
+            println!("network verified: {:?}", network);
+        }
+        RpcCommands::EstimateSync {
+            from_block,
+            sample_secs,
+        } => {
+            let mut results = batch_call(
+                rpc_url,
+                &[("get_tip_header", json!([])), ("get_scripts", json!([]))],
+            )?
+            .into_iter();
+            let mut next_result = || results.next().expect("batch result");
+            let tip_header: json_types::HeaderView = serde_json::from_value(next_result())?;
+            let tip_number: u64 = tip_header.inner.number.value();
+            let before: Vec<ScriptStatus> = serde_json::from_value(next_result())?;
+            if from_block > tip_number {
+                return Err(anyhow!(
+                    "the block number {} is greater than the tip number {}",
+                    from_block,
+                    tip_number
+                ));
+            }
+            let start = Instant::now();
+            println!(
+                "sampling the synchronization progress for {} seconds...",
+                sample_secs
+            );
+            thread::sleep(Duration::from_secs(sample_secs));
+            let after = client.get_scripts()?;
+            let elapsed = start.elapsed().as_secs_f64();
+            // Only the scripts behind the tip are synchronizing the history blocks
+            let progress = before
+                .iter()
+                .filter(|old| old.block_number.value() < tip_number)
+                .filter_map(|old| {
+                    after
+                        .iter()
+                        .find(|new| is_same_script(old, new))
+                        .map(|new| {
+                            new.block_number
+                                .value()
+                                .saturating_sub(old.block_number.value())
+                        })
+                })
+                .max();
+            let blocks = tip_number - from_block;
+            let sync_rate = progress
+                .filter(|progress| *progress > 0)
+                .map(|progress| progress as f64 / elapsed);
+            if sync_rate.is_none() {
+                eprintln!(
+                    "WARNING: no registered script is synchronizing, the rate can not be measured"
+                );
+            }
+            output.print_json(&SyncEstimate {
+                from_block: from_block.into(),
+                tip_number: tip_number.into(),
+                blocks,
+                sync_rate,
+                estimated_seconds: sync_rate.map(|rate| (blocks as f64 / rate).ceil() as u64),
+            })?;
+        }
+        RpcCommands::SyncedRange { address } => {
+            // The reads are independent, fetch them in one round trip
+            let mut results = batch_call(
+                rpc_url,
+                &[
+                    ("get_tip_header", json!([])),
+                    ("get_genesis_block", json!([])),
+                    ("get_scripts", json!([])),
+                ],
+            )?
+            .into_iter();
+            let mut next_result = || results.next().expect("batch result");
+            let tip_header: json_types::HeaderView = serde_json::from_value(next_result())?;
+            let tip_number: u64 = tip_header.inner.number.value();
+            let network = network_type_of_genesis(serde_json::from_value(next_result())?);
+            let mut scripts: Vec<ScriptStatus> = serde_json::from_value(next_result())?;
+            let address_script: Option<json_types::Script> =
+                address.as_ref().map(|address| Script::from(address).into());
+            if let Some(script) = address_script.as_ref() {
+                scripts.retain(|status| {
+                    matches!(status.script_type, ScriptType::Lock) && &status.script == script
+                });
+                if scripts.is_empty() {
+                    return Err(anyhow!("address not registered, you may use `rpc set-scripts` subcommand to register the address"));
+                }
+            }
+            let ranges = scripts
+                .into_iter()
+                .map(|status| {
+                    let synced_number = status.block_number.value();
+                    let address = matches!(status.script_type, ScriptType::Lock).then(|| {
+                        let payload = AddressPayload::from(Script::from(status.script.clone()));
+                        Address::new(network, payload, true).to_string()
+                    });
+                    ScriptSyncedRange {
+                        script: status.script,
+                        script_type: status.script_type,
+                        address,
+                        synced_number: synced_number.into(),
+                        tip_number: tip_number.into(),
+                        behind: tip_number.saturating_sub(synced_number).into(),
+                    }
+                })
+                .collect::<Vec<_>>();
+            output.print_json(&ranges)?;
         }
     }
     Ok(())
 }
 
+/// Fetch all the pages start from `after`. When an error occurred in the
+/// middle, the objects already fetched are still printed (marked as
+/// incomplete, the `last_cursor` can be used to resume) before the error is
+/// returned. Only the objects matched by `keep` are outputted.
+fn fetch_all_pages<T, F, K>(
+    output: &Output,
+    limit: u32,
+    mut after: Option<json_types::JsonBytes>,
+    mut fetch_page: F,
+    keep: K,
+) -> Result<(), Error>
+where
+    T: Serialize,
+    F: FnMut(u32, Option<json_types::JsonBytes>) -> Result<Pagination<T>, Error>,
+    K: Fn(&T) -> bool,
+{
+    if limit == 0 {
+        return Err(anyhow!(
+            "`--limit` must be greater than 0 when `--all` is used"
+        ));
+    }
+    // A page shorter than the limit ends the pagination, so the limit must not
+    // be truncated by the node
+    let limit = check_limit(limit);
+    let mut objects = Vec::new();
+    let result = loop {
+        match fetch_page(limit, after.clone()) {
+            Ok(page) => {
+                let is_last_page = page.objects.len() < limit as usize;
+                objects.extend(page.objects.into_iter().filter(|obj| keep(obj)));
+                after = Some(page.last_cursor);
+                if is_last_page {
+                    break Ok(());
+                }
+            }
+            Err(err) => break Err(err),
+        }
+    };
+    output.print_json(&serde_json::json!({
+        "objects": objects,
+        "last_cursor": after,
+        "complete": result.is_ok(),
+    }))?;
+    result.map_err(|err| {
+        anyhow!(
+            "fetch pages error (incomplete result, {} objects fetched): {}",
+            objects.len(),
+            err
+        )
+    })
+}
+
+/// Load the transaction from the ckb-cli transaction file:
+///   * `ckb-cli tx` file: {"transaction": .., "multisig_configs": .., "signatures": ..}
+///   * `ckb-cli mock-tx` file: {"mock_info": .., "tx": ..}
+fn load_ckb_cli_tx(rpc_url: &str, path: &Path) -> Result<TransactionView, Error> {
+    let content = fs::read_to_string(path)?;
+    let value: serde_json::Value = serde_json::from_str(&content)?;
+    if value.get("mock_info").is_some() {
+        let tx: json_types::Transaction = serde_json::from_value(value["tx"].clone())
+            .map_err(|err| anyhow!("parse ckb-cli mock-tx file error: {}", err))?;
+        return Ok(packed::Transaction::from(tx).into_view());
+    }
+    let ckb_cli_tx: CkbCliTx = serde_json::from_value(value)
+        .map_err(|err| anyhow!("parse ckb-cli tx file error: {}", err))?;
+    ckb_cli_tx.into_tx(&LightClientTransactionDependencyProvider::new(rpc_url))
+}
+
+/// Parse the script status from "ADDR,NUM[,lock|type]", the ADDR can also be
+/// a script in "CODE_HASH:HASH_TYPE:ARGS" format.
 fn parse_addr_script(input: &str) -> Result<ScriptStatus, Error> {
-    let parts = input.split(',').collect::<Vec<_>>();
-    if parts.len() != 2 {
+    let parts = input.split(',').map(str::trim).collect::<Vec<_>>();
+    if parts.len() != 2 && parts.len() != 3 {
         return Err(anyhow!("invalid script status: {}", input));
     }
-    let address = Address::from_str(parts[0])
-        .map_err(|err| anyhow!("parse script status address error: {}", err))?;
-    let script: ckb_jsonrpc_types::Script = Script::from(&address).into();
+    let script = if parts[0].contains(':') {
+        parse_script(parts[0])?
+    } else {
+        let address = Address::from_str(parts[0])
+            .map_err(|err| anyhow!("parse script status address error: {}", err))?;
+        Script::from(&address).into()
+    };
     let block_number = u64::from_str(parts[1])
         .map_err(|err| anyhow!("parse script status block number error: {}", err))?;
+    let script_type = match parts.get(2).copied() {
+        None | Some("lock") => ScriptType::Lock,
+        Some("type") => ScriptType::Type,
+        Some(other) => {
+            return Err(anyhow!(
+                "invalid script type: {}, expected lock or type",
+                other
+            ))
+        }
+    };
     Ok(ScriptStatus {
         script,
-        script_type: ScriptType::Lock,
+        script_type,
         block_number: block_number.into(),
     })
 }
 
+/// Parse the script from "CODE_HASH:HASH_TYPE:ARGS", the hash type is one of
+/// data, type and data1.
+fn parse_script(input: &str) -> Result<json_types::Script, Error> {
+    let parts = input.split(':').collect::<Vec<_>>();
+    if parts.len() != 3 {
+        return Err(anyhow!(
+            "invalid script: {}, format: CODE_HASH:HASH_TYPE:ARGS",
+            input
+        ));
+    }
+    let code_hash = H256::from_str(remove0x(parts[0]))
+        .map_err(|err| anyhow!("invalid script code hash {}: {}", parts[0], err))?;
+    let hash_type = match parts[1] {
+        "data" => json_types::ScriptHashType::Data,
+        "type" => json_types::ScriptHashType::Type,
+        "data1" => json_types::ScriptHashType::Data1,
+        other => {
+            return Err(anyhow!(
+                "invalid script hash type: {}, expected data, type or data1",
+                other
+            ))
+        }
+    };
+    let args = hex::decode(remove0x(parts[2]))
+        .map_err(|err| anyhow!("invalid script args {}: {}", parts[2], err))?;
+    Ok(json_types::Script {
+        code_hash,
+        hash_type,
+        args: json_types::JsonBytes::from_vec(args),
+    })
+}
+
+/// Print the added (+), removed (-) and changed (~, the block number) scripts,
+/// return the number of the changes.
+fn print_scripts_diff(current: &[ScriptStatus], requested: &[ScriptStatus]) -> usize {
+    let describe = |status: &ScriptStatus| {
+        format!(
+            "{} {}",
+            match status.script_type {
+                ScriptType::Lock => "lock",
+                ScriptType::Type => "type",
+            },
+            serde_json::to_string(&status.script).unwrap()
+        )
+    };
+    let mut changes = 0;
+    for new in requested {
+        match current.iter().find(|old| is_same_script(old, new)) {
+            None => {
+                println!("+ {} @ {}", describe(new), new.block_number.value());
+                changes += 1;
+            }
+            Some(old) if old.block_number != new.block_number => {
+                println!(
+                    "~ {} @ {} -> {}",
+                    describe(new),
+                    old.block_number.value(),
+                    new.block_number.value()
+                );
+                changes += 1;
+            }
+            Some(_) => {}
+        }
+    }
+    for old in current {
+        if !requested.iter().any(|new| is_same_script(old, new)) {
+            println!("- {} @ {}", describe(old), old.block_number.value());
+            changes += 1;
+        }
+    }
+    if changes == 0 {
+        println!("no difference");
+    }
+    changes
+}
+
+fn is_same_script(a: &ScriptStatus, b: &ScriptStatus) -> bool {
+    let same_type = matches!(
+        (&a.script_type, &b.script_type),
+        (ScriptType::Lock, ScriptType::Lock) | (ScriptType::Type, ScriptType::Type)
+    );
+    same_type && a.script == b.script
+}
+
 pub fn print_example_search_key(
     with_filter: bool,
     get_transactions: bool,
     get_cells: bool,
     get_cells_capacity: bool,
-) {
+    output: &Output,
+) -> Result<(), Error> {
     assert!(!get_transactions || !get_cells);
     assert!(!get_cells || !get_cells_capacity);
     assert!(!get_transactions || !get_cells_capacity);
@@ -290,5 +1152,5 @@ pub fn print_example_search_key(
         map.remove("with_data");
         map.remove("group_by_transaction");
     }
-    println!("{}", serde_json::to_string_pretty(&value).unwrap());
+    output.print_json(&value)
 }
@@ -0,0 +1,87 @@
+//! The well-known scripts registry, resolve the script name to the code hash
+//! (and hash type) of the network.
+use ckb_sdk::{
+    constants::{DAO_TYPE_HASH, MULTISIG_TYPE_HASH, SIGHASH_TYPE_HASH},
+    NetworkType,
+};
+use ckb_types::{core::ScriptHashType, h256, packed::Script, prelude::*, H256};
+use clap::ValueEnum;
+
+#[derive(ValueEnum, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum ScriptName {
+    Sighash,
+    Multisig,
+    Dao,
+    Sudt,
+    Xudt,
+    AnyoneCanPay,
+}
+
+impl ScriptName {
+    /// The code hash and hash type of the script in the network (None if the
+    /// script is not deployed with a known code hash, e.g. in a dev chain)
+    pub fn code_hash(self, network: NetworkType) -> Option<(H256, ScriptHashType)> {
+        let is_mainnet = match network {
+            NetworkType::Mainnet => true,
+            NetworkType::Testnet => false,
+            _ => {
+                // The system scripts are in the genesis block, the type hash is
+                // the same in all the chains
+                return match self {
+                    ScriptName::Sighash => Some((SIGHASH_TYPE_HASH, ScriptHashType::Type)),
+                    ScriptName::Multisig => Some((MULTISIG_TYPE_HASH, ScriptHashType::Type)),
+                    ScriptName::Dao => Some((DAO_TYPE_HASH, ScriptHashType::Type)),
+                    _ => None,
+                };
+            }
+        };
+        let code_hash = match (self, is_mainnet) {
+            (ScriptName::Sighash, _) => (SIGHASH_TYPE_HASH, ScriptHashType::Type),
+            (ScriptName::Multisig, _) => (MULTISIG_TYPE_HASH, ScriptHashType::Type),
+            (ScriptName::Dao, _) => (DAO_TYPE_HASH, ScriptHashType::Type),
+            (ScriptName::Sudt, true) => (
+                h256!("0x5e7a36a77e68eecc013dfa2fe6a23f3b6c344b04005808694ae6dd45eea4cfd5"),
+                ScriptHashType::Type,
+            ),
+            (ScriptName::Sudt, false) => (
+                h256!("0xc5e5dcf215925f7ef4dfaf5f4b4f105bc321c02776d6e7d52a1db3fcd9d011a4"),
+                ScriptHashType::Type,
+            ),
+            (ScriptName::Xudt, true) => (
+                h256!("0x50bd8d6680b8b9cf98b73f3c08faf8b2a21914311954118ad6609be6e78a1b95"),
+                ScriptHashType::Data1,
+            ),
+            (ScriptName::Xudt, false) => (
+                h256!("0x25c29dc317811a6f6f3985a7a9ebc4838bd388d19d0feeecf0bcd60f6c0975bb"),
+                ScriptHashType::Type,
+            ),
+            (ScriptName::AnyoneCanPay, true) => (
+                h256!("0xd369597ff47f29fbc0d47d2e3775370d1250b85140c670e4718af712983a2354"),
+                ScriptHashType::Type,
+            ),
+            (ScriptName::AnyoneCanPay, false) => (
+                h256!("0x3419a1c09eb2567f6552ee7a8ecffd64155cffe0f1796e6e61ec088d740c1356"),
+                ScriptHashType::Type,
+            ),
+        };
+        Some(code_hash)
+    }
+
+    /// Whether the script can be used as a lock script (the others are type scripts)
+    pub fn is_lock(self) -> bool {
+        matches!(
+            self,
+            ScriptName::Sighash | ScriptName::Multisig | ScriptName::AnyoneCanPay
+        )
+    }
+
+    /// Whether the script is an instance of this script (code hash and hash
+    /// type matched, any args)
+    pub fn is_instance(self, network: NetworkType, script: &Script) -> bool {
+        self.code_hash(network)
+            .is_some_and(|(code_hash, hash_type)| {
+                let script_code_hash: H256 = script.code_hash().unpack();
+                script_code_hash == code_hash && script.hash_type().as_slice() == [hash_type as u8]
+            })
+    }
+}
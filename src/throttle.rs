@@ -0,0 +1,148 @@
+//! Limit the rate of all the RPC calls of the invocation (`--rpc-rate-limit`).
+//! The RPC url is replaced with a local proxy, so the calls of the SDK cell
+//! collector, dependency providers and resolvers (which create their own RPC
+//! clients) are limited as well.
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Error};
+use reqwest::{blocking::Client, header::CONTENT_TYPE, StatusCode, Url};
+
+/// A token bucket refilled at `rate` tokens per second, holds at most `rate`
+/// tokens (so a burst is at most one second of calls).
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+static LIMITER: Mutex<Option<TokenBucket>> = Mutex::new(None);
+
+/// The max body size of a proxied request
+const MAX_REQUEST_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Start the local proxy which forwards the requests to <rpc_url> at most
+/// <per_sec> requests per second, returns the proxy url (used as the RPC url
+/// of this invocation).
+pub fn start_proxy(rpc_url: &str, per_sec: u32) -> Result<String, Error> {
+    let rate = f64::from(per_sec);
+    *LIMITER.lock().unwrap() = Some(TokenBucket {
+        rate,
+        tokens: rate,
+        last_refill: Instant::now(),
+    });
+    let target =
+        Url::parse(rpc_url).map_err(|err| anyhow!("invalid rpc url {}: {}", rpc_url, err))?;
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let proxy_url = format!("http://{}", listener.local_addr()?);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let target = target.clone();
+            thread::spawn(move || serve(stream, &target));
+        }
+    });
+    Ok(proxy_url)
+}
+
+/// Forward the requests of the connection one by one (the connection is kept
+/// alive by the client).
+fn serve(stream: TcpStream, target: &Url) -> io::Result<()> {
+    let client = Client::new();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    loop {
+        // The request line and the headers, only the body is forwarded
+        let mut content_length = 0;
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|_| invalid("invalid content-length"))?;
+                }
+            }
+        }
+        if content_length > MAX_REQUEST_SIZE {
+            return Err(invalid("request too large"));
+        }
+        let mut body = Vec::new();
+        (&mut reader).take(content_length).read_to_end(&mut body)?;
+        if body.len() as u64 != content_length {
+            return Err(invalid("truncated request"));
+        }
+
+        throttle();
+        let (status, response) = forward(&client, target, body).unwrap_or_else(|err| {
+            // Reported as a JSON-RPC error, so the client shows the reason
+            let response = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": {
+                    "code": -32603,
+                    "message": format!("forward the request to {} error: {}", target, err),
+                },
+            });
+            (StatusCode::OK, response.to_string().into_bytes())
+        });
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or(""),
+            response.len()
+        )?;
+        writer.write_all(&response)?;
+        writer.flush()?;
+    }
+}
+
+fn forward(
+    client: &Client,
+    target: &Url,
+    body: Vec<u8>,
+) -> Result<(StatusCode, Vec<u8>), reqwest::Error> {
+    let response = client
+        .post(target.clone())
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()?;
+    let status = response.status();
+    Ok((status, response.bytes()?.to_vec()))
+}
+
+/// Blocks until the call is allowed
+fn throttle() {
+    let mut limiter = LIMITER.lock().unwrap();
+    let bucket = match limiter.as_mut() {
+        Some(bucket) => bucket,
+        None => return,
+    };
+    let now = Instant::now();
+    let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * bucket.rate).min(bucket.rate);
+    bucket.last_refill = now;
+    if bucket.tokens < 1.0 {
+        let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / bucket.rate);
+        thread::sleep(wait);
+        bucket.tokens = 1.0;
+        bucket.last_refill = Instant::now();
+    }
+    bucket.tokens -= 1.0;
+}
@@ -0,0 +1,1157 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Error};
+use byteorder::{ByteOrder, LittleEndian};
+use ckb_hash::{blake2b_256, new_blake2b};
+use ckb_jsonrpc_types as json_types;
+use ckb_sdk::{
+    constants::{MULTISIG_TYPE_HASH, SIGHASH_TYPE_HASH},
+    rpc::{
+        ckb_light_client::{FetchStatus, TransactionWithHeader},
+        LightClientRpcClient,
+    },
+    traits::{LightClientTransactionDependencyProvider, TransactionDependencyProvider},
+    types::ScriptGroup,
+    unlock::generate_message,
+    util::calculate_dao_maximum_withdraw4,
+    Address, AddressPayload, NetworkType, Since, SinceType, SECP256K1,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        Capacity, EpochNumberWithFraction, FeeRate, HeaderView, ScriptHashType, TransactionView,
+    },
+    packed::{self, WitnessArgs},
+    prelude::*,
+    H160, H256,
+};
+use clap::{ArgGroup, Subcommand, ValueEnum};
+use image::Luma;
+use qrcode::{render::unicode, QrCode};
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::common::{parse_out_point, remove0x, HexH256};
+use crate::dao::{is_dao_output, DaoField};
+use crate::output::Output;
+use crate::rpc::get_network_type;
+use crate::wallet::{
+    resolve_input_cells, BundleGroupType, CellDepsFile, TransferBundle, DEFAULT_FEE_RATE,
+};
+
+#[derive(Subcommand, Debug)]
+pub enum UtilCommands {
+    /// Dump the system scripts cell deps (resolved from genesis block) into a file,
+    /// the file can be loaded by `--cell-deps-file` option when building transactions
+    DumpCellDeps {
+        /// The output file path
+        #[arg(long, value_name = "FILE")]
+        output: PathBuf,
+    },
+    /// Compute the sighash-all messages of the sighash/multisig lock groups
+    /// in an unsigned transaction (for signing by external signers)
+    TxSighash {
+        /// The transaction file (json), or the transfer bundle file
+        #[arg(long, value_name = "FILE")]
+        transaction: PathBuf,
+        /// Resolve the input cells by the light client (required if the file is not
+        /// a transfer bundle, the input transactions must be fetched)
+        #[arg(long)]
+        resolve: bool,
+        /// The witness field which holds the signature (zeroed when computing the
+        /// message), for the custom locks signed externally (the sighash/multisig
+        /// locks signed by `transfer`/`dao` always read `lock`)
+        #[arg(long, value_enum, default_value = "lock")]
+        witness_field: WitnessField,
+    },
+    /// Fill the signatures (signed by external signers) into the witnesses of
+    /// an unsigned transaction
+    AssembleTx {
+        /// The transaction file (json), or the transfer bundle file
+        #[arg(long, value_name = "FILE")]
+        transaction: PathBuf,
+        /// Resolve the input cells by the light client (required if the file is not
+        /// a transfer bundle, the input transactions must be fetched)
+        #[arg(long)]
+        resolve: bool,
+        /// The signature of a lock group (the group index is from `util tx-sighash`),
+        /// a multisig group requires <threshold> signatures (in the order of the multisig config)
+        #[arg(long, value_name = "INDEX:HEX", required = true)]
+        signature: Vec<String>,
+        /// Normalize the high-S signatures to low-S (default reject them)
+        #[arg(long)]
+        normalize_s: bool,
+        /// The witness field which receives the signature, for the custom locks
+        /// signed externally (the same as `util tx-sighash --witness-field`)
+        #[arg(long, value_enum, default_value = "lock")]
+        witness_field: WitnessField,
+        /// Save the signed transaction to the file instead of printing it
+        #[arg(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// List and decode the outputs of a transaction
+    TxOutputs {
+        #[arg(long, value_name = "H256")]
+        tx_hash: HexH256,
+    },
+    /// Compute the minimal fee of a transaction at the fee rate, and check whether
+    /// the actual fee (inputs capacity - outputs capacity) meets it
+    EstimateFee {
+        /// The transaction file (json), or the transfer bundle file
+        #[arg(long, value_name = "FILE")]
+        transaction: PathBuf,
+        /// Resolve the input cells by the light client (required if the file is not
+        /// a transfer bundle, the input transactions must be fetched)
+        #[arg(long)]
+        resolve: bool,
+        /// The fee rate (unit: shannons/KB)
+        #[arg(long, value_name = "RATE", default_value_t = DEFAULT_FEE_RATE)]
+        fee_rate: u64,
+    },
+    /// Compute the effective fee rate (unit: shannons/KB) of a committed
+    /// transaction, the input cells are resolved by the light client
+    TxFeeRate {
+        #[arg(long, value_name = "H256")]
+        tx_hash: HexH256,
+    },
+    /// Show the total, occupied (minimal) and free capacity of a cell
+    CellCapacity {
+        /// Example: 0xd56ed5d4e8984701714de9744a533413f79604b3b91461e2265614829d2005d1-1
+        #[arg(long, value_name = "OUT-POINT")]
+        out_point: String,
+    },
+    /// Decode the `dao` field (c, ar, s, u) of a block header
+    #[command(group(ArgGroup::new("header").required(true).args(["block_hash", "header_file"])))]
+    DecodeDao {
+        /// Fetch the header by the block hash
+        #[arg(long, value_name = "H256")]
+        block_hash: Option<HexH256>,
+        /// The header file (json, the output of `rpc get-header`)
+        #[arg(long, value_name = "FILE")]
+        header_file: Option<PathBuf>,
+    },
+    /// Render the address as a QR code in the terminal
+    Qr {
+        #[arg(long, value_name = "ADDR")]
+        address: Address,
+        /// Also save the QR code as a PNG image
+        #[arg(long, value_name = "FILE")]
+        png: Option<PathBuf>,
+    },
+    /// Encode a since value (of transaction input)
+    EncodeSince {
+        #[arg(long, value_enum)]
+        kind: SinceKind,
+        /// The value: block number, epoch ("NUMBER" or "NUMBER,INDEX,LENGTH"),
+        /// timestamp in seconds (or "now" for time-abs), the relative kinds use the
+        /// same formats
+        #[arg(long, value_name = "V")]
+        value: String,
+    },
+    /// Decode a since value and explain it
+    DecodeSince {
+        /// The since value (hex string with 0x prefix or decimal)
+        #[arg(long, value_name = "HEX")]
+        value: String,
+    },
+    /// Compute the CKB blake2b_256 hash (the hash of code hash and type id) of the data
+    #[command(group(ArgGroup::new("input").required(true).args(["data_file", "hex"])))]
+    Blake2b {
+        /// The file of the data (e.g. a script binary)
+        #[arg(long, value_name = "FILE")]
+        data_file: Option<PathBuf>,
+        /// The data as a hex string
+        #[arg(long, value_name = "HEX")]
+        hex: Option<String>,
+    },
+}
+
+/// The value part (lower 56 bits) of the since field
+const SINCE_VALUE_MASK: u64 = 0x00FF_FFFF_FFFF_FFFF;
+
+/// The field of `WitnessArgs` holds the signature, only selectable in the
+/// external signing utilities (`transfer` and `dao` only sign the sighash/multisig
+/// locks, which always read the signature from `lock`)
+#[derive(ValueEnum, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum WitnessField {
+    Lock,
+    InputType,
+    OutputType,
+}
+
+#[derive(ValueEnum, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum SinceKind {
+    BlockAbs,
+    BlockRel,
+    EpochAbs,
+    EpochRel,
+    TimeAbs,
+    TimeRel,
+}
+
+pub fn invoke(
+    rpc_url: &str,
+    cmd: UtilCommands,
+    output: &Output,
+    _debug: bool,
+) -> Result<(), Error> {
+    let mut client = LightClientRpcClient::new(rpc_url);
+    match cmd {
+        UtilCommands::DumpCellDeps {
+            output: output_path,
+        } => {
+            let genesis_block = client.get_genesis_block()?.into();
+            let cell_deps = CellDepsFile::from_genesis(&genesis_block)?;
+            fs::write(
+                &output_path,
+                serde_json::to_string_pretty(&cell_deps).unwrap(),
+            )?;
+            println!("cell deps dumped to: {}", output_path.display());
+        }
+        UtilCommands::TxSighash {
+            transaction,
+            resolve,
+            witness_field,
+        } => {
+            let (tx, lock_groups) = load_tx_lock_groups(rpc_url, &transaction, resolve)?;
+            output.print_json(&sighash_messages(&tx, &lock_groups, witness_field)?)?;
+        }
+        UtilCommands::AssembleTx {
+            transaction,
+            resolve,
+            signature,
+            normalize_s,
+            witness_field,
+            output: output_path,
+        } => {
+            let (tx, lock_groups) = load_tx_lock_groups(rpc_url, &transaction, resolve)?;
+            let mut signatures: Vec<Vec<Bytes>> = vec![Vec::new(); lock_groups.len()];
+            for item in &signature {
+                let (group_index, signature) = parse_signature(item)?;
+                signatures
+                    .get_mut(group_index)
+                    .ok_or_else(|| anyhow!("lock group index out of range: {}", group_index))?
+                    .push(signature);
+            }
+            let mut witnesses: Vec<packed::Bytes> = tx.witnesses().into_iter().collect();
+            for (group_index, (group, signatures)) in lock_groups.iter().zip(signatures).enumerate()
+            {
+                let lock_type = SignLockType::from_script(&group.script).ok_or_else(|| {
+                    anyhow!(
+                        "lock group {} is neither sighash nor multisig lock, can not be assembled",
+                        group_index
+                    )
+                })?;
+                if signatures.is_empty() {
+                    return Err(anyhow!(
+                        "signature of lock group {} is not provided",
+                        group_index
+                    ));
+                }
+                let witness_lock = get_witness_field(&tx, group, witness_field)?;
+                let message = generate_field_message(
+                    &tx,
+                    group,
+                    witness_field,
+                    lock_type.zero_lock(&witness_lock)?,
+                )?;
+                let mut signers = Vec::with_capacity(signatures.len());
+                let mut checked_signatures = Vec::with_capacity(signatures.len());
+                for signature in &signatures {
+                    let (signature, signer) =
+                        check_signature(signature, message.as_ref(), normalize_s)
+                            .map_err(|err| anyhow!("lock group {}: {}", group_index, err))?;
+                    signers.push(signer);
+                    checked_signatures.push(signature);
+                }
+                let signatures = checked_signatures;
+                let new_lock = match lock_type {
+                    SignLockType::Sighash => {
+                        if signatures.len() != 1 {
+                            return Err(anyhow!(
+                                "sighash lock group {} requires exactly 1 signature",
+                                group_index
+                            ));
+                        }
+                        if signers[0].as_bytes() != group.script.args().raw_data().as_ref() {
+                            return Err(anyhow!(
+                                "the signature of lock group {} is not signed by the lock args owner, recovered: {:#x}",
+                                group_index,
+                                signers[0]
+                            ));
+                        }
+                        signatures[0].clone()
+                    }
+                    SignLockType::Multisig => {
+                        let config_len = lock_type.config_len(&witness_lock)?;
+                        let threshold = witness_lock[2] as usize;
+                        if signatures.len() != threshold {
+                            return Err(anyhow!(
+                                "multisig lock group {} requires {} signatures, got {}",
+                                group_index,
+                                threshold,
+                                signatures.len()
+                            ));
+                        }
+                        check_multisig_signers(&witness_lock[0..config_len], &signers)
+                            .map_err(|err| anyhow!("lock group {}: {}", group_index, err))?;
+                        let mut new_lock = witness_lock[0..config_len].to_vec();
+                        for signature in &signatures {
+                            new_lock.extend_from_slice(signature.as_ref());
+                        }
+                        Bytes::from(new_lock)
+                    }
+                };
+                let witness_idx = group.input_indices[0];
+                let witness_data = witnesses[witness_idx].raw_data();
+                let witness_args = if witness_data.is_empty() {
+                    WitnessArgs::default()
+                } else {
+                    WitnessArgs::from_slice(witness_data.as_ref())
+                        .map_err(|err| anyhow!("invalid witness args: {}", err))?
+                };
+                witnesses[witness_idx] = set_witness_field(witness_args, witness_field, new_lock)
+                    .as_bytes()
+                    .pack();
+            }
+            let tx = tx.as_advanced_builder().set_witnesses(witnesses).build();
+            let json_tx = json_types::Transaction::from(tx.data());
+            if let Some(path) = output_path {
+                fs::write(&path, serde_json::to_string_pretty(&json_tx).unwrap())?;
+                println!("signed transaction saved to: {}", path.display());
+            } else {
+                output.print_json(&json_tx)?;
+            }
+        }
+        UtilCommands::TxOutputs { tx_hash } => {
+            let tx = packed::Transaction::from(
+                get_any_transaction(&mut client, tx_hash.0)?
+                    .transaction
+                    .inner,
+            )
+            .into_view();
+            let network = get_network_type(&mut client)?;
+            let outputs = tx
+                .outputs_with_data_iter()
+                .enumerate()
+                .map(|(index, (cell_output, data))| {
+                    let capacity: u64 = cell_output.capacity().unpack();
+                    let lock = cell_output.lock();
+                    // The hash type may not be supported by this version of address format
+                    let lock_address = ScriptHashType::try_from(lock.hash_type())
+                        .ok()
+                        .map(|_| Address::new(network, AddressPayload::from(lock.clone()), true));
+                    TxOutputInfo {
+                        index,
+                        capacity: output.format_capacity(capacity),
+                        capacity_shannons: capacity,
+                        lock_address: lock_address.map(|addr| addr.to_string()),
+                        lock_hash: lock.calc_script_hash().unpack(),
+                        type_hash: cell_output
+                            .type_()
+                            .to_opt()
+                            .map(|script| script.calc_script_hash().unpack()),
+                        data_len: data.len(),
+                    }
+                })
+                .collect::<Vec<_>>();
+            output.print_json(&outputs)?;
+        }
+        UtilCommands::EstimateFee {
+            transaction,
+            resolve,
+            fee_rate,
+        } => {
+            let (tx, tx_dep_provider) = load_tx(rpc_url, &transaction, resolve)?;
+            let size = tx.data().as_reader().serialized_size_in_block();
+            let min_fee = FeeRate::from_u64(fee_rate).fee(size).as_u64();
+            let actual_fee = tx_fee(&mut client, &tx, tx_dep_provider.as_ref())?;
+            output.print_json(&serde_json::json!({
+                "size": size,
+                "fee_rate": fee_rate,
+                "min_fee": output.format_capacity(min_fee),
+                "actual_fee": output.format_capacity(actual_fee),
+                "sufficient": actual_fee >= min_fee,
+            }))?;
+        }
+        UtilCommands::TxFeeRate { tx_hash } => {
+            let tx_with_header = get_any_transaction(&mut client, tx_hash.0)?;
+            let tx = packed::Transaction::from(tx_with_header.transaction.inner).into_view();
+            if tx.is_cellbase() {
+                return Err(anyhow!("the cellbase transaction has no fee"));
+            }
+            let tx_dep_provider = LightClientTransactionDependencyProvider::new(rpc_url);
+            let size = tx.data().as_reader().serialized_size_in_block();
+            let fee = tx_fee(&mut client, &tx, &tx_dep_provider)?;
+            output.print_json(&serde_json::json!({
+                "block_number": tx_with_header.header.inner.number,
+                "size": size,
+                "fee": output.format_capacity(fee),
+                "fee_shannons": fee,
+                "fee_rate": fee * 1000 / size as u64,
+            }))?;
+        }
+        UtilCommands::CellCapacity { out_point } => {
+            let out_point = parse_out_point(&out_point)?;
+            let tx_hash: H256 = out_point.tx_hash().unpack();
+            let index: u32 = out_point.index().unpack();
+            let tx = packed::Transaction::from(
+                get_any_transaction(&mut client, tx_hash.clone())?
+                    .transaction
+                    .inner,
+            )
+            .into_view();
+            let (cell_output, data) = tx
+                .output_with_data(index as usize)
+                .ok_or_else(|| anyhow!("output not found: {:#x}-{}", tx_hash, index))?;
+            let capacity: u64 = cell_output.capacity().unpack();
+            let occupied_capacity = cell_output
+                .occupied_capacity(Capacity::bytes(data.len())?)?
+                .as_u64();
+            let free_capacity = capacity.saturating_sub(occupied_capacity);
+            output.print_json(&CellCapacityInfo {
+                capacity: output.format_capacity(capacity),
+                capacity_shannons: capacity,
+                occupied_capacity: output.format_capacity(occupied_capacity),
+                occupied_capacity_shannons: occupied_capacity,
+                free_capacity: output.format_capacity(free_capacity),
+                free_capacity_shannons: free_capacity,
+                data_len: data.len(),
+            })?;
+        }
+        UtilCommands::DecodeDao {
+            block_hash,
+            header_file,
+        } => {
+            let header: json_types::HeaderView = if let Some(path) = header_file {
+                let content = fs::read_to_string(&path)?;
+                serde_json::from_str(&content)
+                    .map_err(|err| anyhow!("parse header file error: {}", err))?
+            } else {
+                let block_hash = block_hash.expect("block hash").0;
+                client
+                    .get_header(block_hash.clone())?
+                    .ok_or_else(|| anyhow!("header not found: {:#x}", block_hash))?
+            };
+            let header = HeaderView::from(header);
+            let block_hash: H256 = header.hash().unpack();
+            let dao = DaoField::from_header(&header);
+            output.print_json(&serde_json::json!({
+                "block_number": header.number(),
+                "block_hash": block_hash,
+                "dao": format!("{:#x}", header.dao()),
+                "c": output.format_capacity(dao.c),
+                "ar": dao.ar,
+                "ar_ratio": dao.ar_ratio(),
+                "s": output.format_capacity(dao.s),
+                "u": output.format_capacity(dao.u),
+            }))?;
+        }
+        UtilCommands::Qr { address, png } => {
+            let code = QrCode::new(address.to_string().as_bytes())
+                .map_err(|err| anyhow!("encode QR code error: {}", err))?;
+            // Inverted colors, so that it's readable in the dark terminal
+            let text = code
+                .render::<unicode::Dense1x2>()
+                .dark_color(unicode::Dense1x2::Light)
+                .light_color(unicode::Dense1x2::Dark)
+                .build();
+            println!("{}", text);
+            println!("{}", address);
+            if let Some(path) = png {
+                code.render::<Luma<u8>>()
+                    .min_dimensions(256, 256)
+                    .build()
+                    .save(&path)
+                    .map_err(|err| anyhow!("save QR code image error: {}", err))?;
+                println!("QR code image saved to: {}", path.display());
+            }
+        }
+        UtilCommands::EncodeSince { kind, value } => {
+            let since = encode_since(kind, &value)?;
+            println!("{:#018x}", since.value());
+        }
+        UtilCommands::DecodeSince { value } => {
+            let since = if let Some(hex_str) = value.strip_prefix("0x") {
+                u64::from_str_radix(hex_str, 16)
+            } else {
+                value.parse::<u64>()
+            }
+            .map_err(|err| anyhow!("invalid since value {}: {}", value, err))?;
+            println!("{}", explain_since(Since::from_raw_value(since))?);
+        }
+        UtilCommands::Blake2b { data_file, hex } => {
+            let data = if let Some(path) = data_file {
+                fs::read(&path)
+                    .map_err(|err| anyhow!("read data file {} error: {}", path.display(), err))?
+            } else {
+                let hex = hex.expect("hex");
+                hex::decode(remove0x(&hex)).map_err(|err| anyhow!("invalid hex data: {}", err))?
+            };
+            println!("0x{}", hex::encode(blake2b_256(&data)));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct TxOutputInfo {
+    index: usize,
+    capacity: String,
+    capacity_shannons: u64,
+    lock_address: Option<String>,
+    lock_hash: H256,
+    type_hash: Option<H256>,
+    data_len: usize,
+}
+
+#[derive(Serialize)]
+struct CellCapacityInfo {
+    capacity: String,
+    capacity_shannons: u64,
+    occupied_capacity: String,
+    occupied_capacity_shannons: u64,
+    /// The capacity above the occupied capacity
+    free_capacity: String,
+    free_capacity_shannons: u64,
+    data_len: usize,
+}
+
+/// Get the transaction (with the header of the block it committed in) from
+/// the light client, fetch it from the remote node if it's not related to the
+/// registered scripts.
+pub fn get_any_transaction(
+    client: &mut LightClientRpcClient,
+    tx_hash: H256,
+) -> Result<TransactionWithHeader, Error> {
+    if let Some(tx_with_header) = client.get_transaction(tx_hash.clone())? {
+        return Ok(tx_with_header);
+    }
+    match client.fetch_transaction(tx_hash.clone())? {
+        FetchStatus::Fetched { data } => Ok(data),
+        FetchStatus::NotFound => Err(anyhow!("transaction not found: {:#x}", tx_hash)),
+        _ => Err(anyhow!(
+            "transaction {:#x} is being fetched, please try again later",
+            tx_hash
+        )),
+    }
+}
+
+fn encode_since(kind: SinceKind, value: &str) -> Result<Since, Error> {
+    let parse_u64 = |value: &str| {
+        value
+            .trim()
+            .parse::<u64>()
+            .map_err(|err| anyhow!("invalid since value {}: {}", value, err))
+    };
+    let (since_type, raw_value, is_relative) = match kind {
+        SinceKind::BlockAbs | SinceKind::BlockRel => (
+            SinceType::BlockNumber,
+            parse_u64(value)?,
+            kind == SinceKind::BlockRel,
+        ),
+        SinceKind::EpochAbs | SinceKind::EpochRel => {
+            let parts = value.split(',').collect::<Vec<_>>();
+            let (number, index, length) = match parts[..] {
+                [number] => (parse_u64(number)?, 0, 1),
+                [number, index, length] => {
+                    (parse_u64(number)?, parse_u64(index)?, parse_u64(length)?)
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "invalid epoch value {}, format: NUMBER or NUMBER,INDEX,LENGTH",
+                        value
+                    ))
+                }
+            };
+            if number > 0xFF_FFFF || length == 0 || length > 0xFFFF || index >= length {
+                return Err(anyhow!(
+                    "invalid epoch value {}, require: NUMBER <= 16777215, 0 < LENGTH <= 65535, INDEX < LENGTH",
+                    value
+                ));
+            }
+            let epoch = EpochNumberWithFraction::new(number, index, length);
+            (
+                SinceType::EpochNumberWithFraction,
+                epoch.full_value(),
+                kind == SinceKind::EpochRel,
+            )
+        }
+        SinceKind::TimeAbs | SinceKind::TimeRel => {
+            let seconds = if kind == SinceKind::TimeAbs && value == "now" {
+                SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()
+            } else {
+                parse_u64(value)?
+            };
+            (SinceType::Timestamp, seconds, kind == SinceKind::TimeRel)
+        }
+    };
+    if raw_value > SINCE_VALUE_MASK {
+        return Err(anyhow!("since value too large: {}", raw_value));
+    }
+    Ok(Since::new(since_type, raw_value, is_relative))
+}
+
+fn explain_since(since: Since) -> Result<String, Error> {
+    if !since.flags_is_valid() {
+        return Err(anyhow!("invalid since flags: {:#018x}", since.value()));
+    }
+    let (since_type, value) = since.extract_metric().expect("valid since flags");
+    let relative = if since.is_relative() {
+        "relative"
+    } else {
+        "absolute"
+    };
+    let explanation = match since_type {
+        SinceType::BlockNumber => format!("{} block number: {}", relative, value),
+        SinceType::EpochNumberWithFraction => {
+            let epoch = EpochNumberWithFraction::from_full_value(value);
+            format!(
+                "{} epoch: {} + {}/{}",
+                relative,
+                epoch.number(),
+                epoch.index(),
+                epoch.length()
+            )
+        }
+        SinceType::Timestamp if since.is_relative() => {
+            format!("relative timestamp: {} seconds", value)
+        }
+        SinceType::Timestamp => format!("absolute timestamp (median time): {} seconds", value),
+    };
+    Ok(explanation)
+}
+
+/// Check the signature can be recovered to a public key, return the
+/// (normalized) signature and the blake160 hash of the public key. A high-S
+/// signature is malleable and will be rejected by the node, it is normalized to
+/// low-S (with the recovery id flipped) only when `normalize_s` is set.
+fn check_signature(
+    signature: &Bytes,
+    message: &[u8],
+    normalize_s: bool,
+) -> Result<(Bytes, H160), Error> {
+    let recid = RecoveryId::from_i32(signature[64] as i32)
+        .map_err(|err| anyhow!("invalid signature recovery id: {}", err))?;
+    let recoverable = RecoverableSignature::from_compact(&signature[0..64], recid)
+        .map_err(|err| anyhow!("invalid signature: {}", err))?;
+    let mut standard = recoverable.to_standard();
+    standard.normalize_s();
+    let normalized = standard.serialize_compact();
+    let recoverable = if normalized[..] == signature[0..64] {
+        recoverable
+    } else if normalize_s {
+        let recid = RecoveryId::from_i32(recid.to_i32() ^ 1)
+            .map_err(|err| anyhow!("invalid signature recovery id: {}", err))?;
+        RecoverableSignature::from_compact(&normalized, recid)
+            .map_err(|err| anyhow!("invalid signature: {}", err))?
+    } else {
+        return Err(anyhow!(
+            "high-S signature (malleable) is not allowed, use `--normalize-s` to normalize it"
+        ));
+    };
+    let message = Message::from_slice(message).expect("32 bytes message");
+    let pubkey = SECP256K1
+        .recover_ecdsa(&message, &recoverable)
+        .map_err(|err| anyhow!("recover public key from signature error: {}", err))?;
+    let hash160 = H160::from_slice(&blake2b_256(&pubkey.serialize()[..])[0..20]).unwrap();
+    let (recid, data) = recoverable.serialize_compact();
+    let mut signature = data.to_vec();
+    signature.push(recid.to_i32() as u8);
+    Ok((Bytes::from(signature), hash160))
+}
+
+/// The multisig signatures must be signed by the keys in the multisig config
+/// (in the same order), and the first <require_first_n> keys are required.
+fn check_multisig_signers(config_data: &[u8], signers: &[H160]) -> Result<(), Error> {
+    let require_first_n = config_data[1] as usize;
+    let sighash_addresses: Vec<&[u8]> = config_data[4..].chunks(20).collect();
+    let mut last_position = None;
+    for signer in signers {
+        let position = sighash_addresses
+            .iter()
+            .position(|addr| *addr == signer.as_bytes())
+            .ok_or_else(|| {
+                anyhow!(
+                    "the signature signer (lock args: {:#x}) is not in the multisig config",
+                    signer
+                )
+            })?;
+        if last_position.map(|last| position <= last).unwrap_or(false) {
+            return Err(anyhow!(
+                "the multisig signatures must be unique and in the order of the multisig config"
+            ));
+        }
+        last_position = Some(position);
+    }
+    let signed_first_n = signers
+        .iter()
+        .zip(sighash_addresses.iter())
+        .take_while(|(signer, addr)| signer.as_bytes() == **addr)
+        .count();
+    if signed_first_n < require_first_n {
+        return Err(anyhow!(
+            "the first {} keys of the multisig config are required to sign",
+            require_first_n
+        ));
+    }
+    Ok(())
+}
+
+/// Parse the signature argument: "<INDEX>:<HEX>"
+fn parse_signature(input: &str) -> Result<(usize, Bytes), Error> {
+    let (index, hex_str) = input
+        .split_once(':')
+        .ok_or_else(|| anyhow!("invalid signature argument (expected INDEX:HEX): {}", input))?;
+    let group_index = index
+        .parse::<usize>()
+        .map_err(|err| anyhow!("invalid lock group index {}: {}", index, err))?;
+    let data = hex::decode(remove0x(hex_str))
+        .map_err(|err| anyhow!("invalid signature hex {}: {}", hex_str, err))?;
+    if data.len() != 65 {
+        return Err(anyhow!(
+            "invalid signature length: {}, expected: 65",
+            data.len()
+        ));
+    }
+    Ok((group_index, Bytes::from(data)))
+}
+
+/// The transaction file of the ckb-cli `tx` subcommands (`--tx-file`)
+#[derive(Serialize, Deserialize)]
+pub struct CkbCliTx {
+    transaction: json_types::Transaction,
+    /// The multisig config hash160 => the config
+    multisig_configs: BTreeMap<String, CkbCliMultisigConfig>,
+    /// The lock args => the signatures
+    signatures: BTreeMap<String, Vec<json_types::JsonBytes>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CkbCliMultisigConfig {
+    sighash_addresses: Vec<String>,
+    require_first_n: u8,
+    threshold: u8,
+}
+
+impl CkbCliTx {
+    /// Convert the built transaction, the signatures and the multisig configs
+    /// are extracted from the witness locks of the sighash/multisig lock groups
+    /// (the zero signatures of the placeholders are skipped).
+    pub fn new(
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+        network: NetworkType,
+    ) -> Result<CkbCliTx, Error> {
+        let mut multisig_configs = BTreeMap::new();
+        let mut signatures = BTreeMap::new();
+        let lock_groups = TransferBundle::gen_script_groups(tx, tx_dep_provider)?
+            .into_iter()
+            .filter(|group| group.group_type == BundleGroupType::Lock)
+            .map(ScriptGroup::from);
+        for group in lock_groups {
+            let lock_type = match SignLockType::from_script(&group.script) {
+                Some(lock_type) => lock_type,
+                None => continue,
+            };
+            let witness_lock = get_witness_field(tx, &group, WitnessField::Lock)?;
+            let config_len = lock_type.config_len(&witness_lock)?;
+            if lock_type == SignLockType::Multisig {
+                let config = &witness_lock[0..config_len];
+                let sighash_addresses = config[4..]
+                    .chunks(20)
+                    .map(|hash| {
+                        let payload = AddressPayload::from_pubkey_hash(H160::from_slice(hash)?);
+                        Ok(Address::new(network, payload, true).to_string())
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let hash160 = H160::from_slice(&blake2b_256(config)[0..20])?;
+                multisig_configs.insert(
+                    format!("{:#x}", hash160),
+                    CkbCliMultisigConfig {
+                        sighash_addresses,
+                        require_first_n: config[1],
+                        threshold: config[2],
+                    },
+                );
+            }
+            let group_signatures: Vec<_> = witness_lock[config_len..]
+                .chunks(65)
+                .filter(|signature| signature.iter().any(|b| *b != 0))
+                .map(|signature| json_types::JsonBytes::from_vec(signature.to_vec()))
+                .collect();
+            if !group_signatures.is_empty() {
+                let lock_args = group.script.args().raw_data();
+                signatures.insert(format!("0x{}", hex::encode(lock_args)), group_signatures);
+            }
+        }
+        Ok(CkbCliTx {
+            transaction: json_types::TransactionView::from(tx.clone()).inner,
+            multisig_configs,
+            signatures,
+        })
+    }
+
+    /// Build the signed transaction: fill the witness locks of the
+    /// sighash/multisig lock groups with the signatures (like `ckb-cli tx send`).
+    pub fn into_tx(
+        self,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<TransactionView, Error> {
+        let tx = packed::Transaction::from(self.transaction).into_view();
+        let mut witnesses: Vec<packed::Bytes> = tx.witnesses().into_iter().collect();
+        let lock_groups = TransferBundle::gen_script_groups(&tx, tx_dep_provider)?
+            .into_iter()
+            .filter(|group| group.group_type == BundleGroupType::Lock)
+            .map(ScriptGroup::from);
+        for group in lock_groups {
+            let lock_type = match SignLockType::from_script(&group.script) {
+                Some(lock_type) => lock_type,
+                None => continue,
+            };
+            let lock_args = format!("0x{}", hex::encode(group.script.args().raw_data()));
+            let signatures = self
+                .signatures
+                .get(&lock_args)
+                .ok_or_else(|| anyhow!("the lock {} is not signed", lock_args))?;
+            let mut new_lock = Vec::new();
+            if lock_type == SignLockType::Multisig {
+                let hash160 = &lock_args[0..42];
+                let config = self
+                    .multisig_configs
+                    .get(hash160)
+                    .ok_or_else(|| anyhow!("multisig config not found: {}", hash160))?;
+                if signatures.len() != config.threshold as usize {
+                    return Err(anyhow!(
+                        "the multisig lock {} requires {} signatures, got {}",
+                        lock_args,
+                        config.threshold,
+                        signatures.len()
+                    ));
+                }
+                new_lock.extend_from_slice(&[
+                    0,
+                    config.require_first_n,
+                    config.threshold,
+                    config.sighash_addresses.len() as u8,
+                ]);
+                for address in &config.sighash_addresses {
+                    let address = Address::from_str(address)
+                        .map_err(|err| anyhow!("invalid sighash address {}: {}", address, err))?;
+                    new_lock.extend_from_slice(address.payload().args().as_ref());
+                }
+            }
+            for signature in signatures {
+                new_lock.extend_from_slice(signature.as_bytes());
+            }
+            let witness_idx = group.input_indices[0];
+            let witness_data = witnesses
+                .get(witness_idx)
+                .map(|witness| witness.raw_data())
+                .ok_or_else(|| anyhow!("witness not enough"))?;
+            let witness_args = if witness_data.is_empty() {
+                WitnessArgs::default()
+            } else {
+                WitnessArgs::from_slice(witness_data.as_ref())
+                    .map_err(|err| anyhow!("invalid witness args: {}", err))?
+            };
+            witnesses[witness_idx] =
+                set_witness_field(witness_args, WitnessField::Lock, Bytes::from(new_lock))
+                    .as_bytes()
+                    .pack();
+        }
+        Ok(tx.as_advanced_builder().set_witnesses(witnesses).build())
+    }
+}
+
+#[derive(Serialize)]
+pub struct SighashMessage {
+    group_index: usize,
+    lock_script: json_types::Script,
+    input_indices: Vec<usize>,
+    message: H256,
+}
+
+/// The sighash-all messages of the sighash/multisig lock groups (the other
+/// lock groups are skipped), the group index is the index in <lock_groups>
+pub fn sighash_messages(
+    tx: &TransactionView,
+    lock_groups: &[ScriptGroup],
+    witness_field: WitnessField,
+) -> Result<Vec<SighashMessage>, Error> {
+    let mut messages = Vec::new();
+    for (group_index, group) in lock_groups.iter().enumerate() {
+        let witness_lock = get_witness_field(tx, group, witness_field)?;
+        let zero_lock = match SignLockType::from_script(&group.script) {
+            Some(lock_type) => lock_type.zero_lock(&witness_lock)?,
+            None => continue,
+        };
+        let message = generate_field_message(tx, group, witness_field, zero_lock)?;
+        messages.push(SighashMessage {
+            group_index,
+            lock_script: group.script.clone().into(),
+            input_indices: group.input_indices.clone(),
+            message: H256::from_slice(message.as_ref()).unwrap(),
+        });
+    }
+    Ok(messages)
+}
+
+/// The lock scripts can be signed by external signers
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SignLockType {
+    Sighash,
+    Multisig,
+}
+
+impl SignLockType {
+    fn from_script(script: &packed::Script) -> Option<SignLockType> {
+        if script.hash_type().as_slice() != [ScriptHashType::Type as u8] {
+            return None;
+        }
+        let code_hash: H256 = script.code_hash().unpack();
+        if code_hash == SIGHASH_TYPE_HASH {
+            Some(SignLockType::Sighash)
+        } else if code_hash == MULTISIG_TYPE_HASH {
+            Some(SignLockType::Multisig)
+        } else {
+            None
+        }
+    }
+
+    /// The length of the multisig config part at the beginning of the witness
+    /// lock (zero for sighash lock).
+    fn config_len(self, witness_lock: &[u8]) -> Result<usize, Error> {
+        match self {
+            SignLockType::Sighash => Ok(0),
+            SignLockType::Multisig => {
+                if witness_lock.len() < 4 {
+                    return Err(anyhow!(
+                        "invalid multisig witness lock (placeholder required)"
+                    ));
+                }
+                let config_len = 4 + 20 * witness_lock[3] as usize;
+                let threshold = witness_lock[2] as usize;
+                if witness_lock.len() != config_len + 65 * threshold {
+                    return Err(anyhow!("invalid multisig witness lock length"));
+                }
+                Ok(config_len)
+            }
+        }
+    }
+
+    /// The witness lock used to compute the signing message: the signature
+    /// part is replaced with zeros.
+    fn zero_lock(self, witness_lock: &[u8]) -> Result<Bytes, Error> {
+        match self {
+            SignLockType::Sighash => Ok(Bytes::from(vec![0u8; 65])),
+            SignLockType::Multisig => {
+                let config_len = self.config_len(witness_lock)?;
+                let mut zero_lock = witness_lock.to_vec();
+                zero_lock[config_len..].iter_mut().for_each(|b| *b = 0);
+                Ok(Bytes::from(zero_lock))
+            }
+        }
+    }
+}
+
+/// The fee of the transaction: inputs capacity - outputs capacity, the
+/// capacity of a NervosDAO withdraw input includes its compensation (the
+/// header deps and the prepare header are fetched from the light client).
+pub fn tx_fee(
+    client: &mut LightClientRpcClient,
+    tx: &TransactionView,
+    tx_dep_provider: &dyn TransactionDependencyProvider,
+) -> Result<u64, Error> {
+    let mut input_capacity = 0;
+    let mut header_deps: Option<Vec<HeaderView>> = None;
+    for cell in resolve_input_cells(tx, tx_dep_provider)? {
+        let output = packed::CellOutput::from(cell.output);
+        let data = cell.output_data.into_bytes();
+        let capacity: u64 = output.capacity().unpack();
+        // The prepared cell keeps the deposit block number as the data
+        if !is_dao_output(&output) || data.len() != 8 || LittleEndian::read_u64(&data) == 0 {
+            input_capacity += capacity;
+            continue;
+        }
+        if header_deps.is_none() {
+            let mut headers = Vec::new();
+            for block_hash in tx.header_deps_iter() {
+                let block_hash: H256 = block_hash.unpack();
+                let header = client
+                    .get_header(block_hash.clone())?
+                    .ok_or_else(|| anyhow!("header dep not found: {:#x}", block_hash))?;
+                headers.push(HeaderView::from(header));
+            }
+            header_deps = Some(headers);
+        }
+        let deposit_number = LittleEndian::read_u64(&data);
+        let deposit_header = header_deps
+            .as_ref()
+            .and_then(|headers| {
+                headers
+                    .iter()
+                    .find(|header| header.number() == deposit_number)
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "the deposit header (block {}) is not in the header deps",
+                    deposit_number
+                )
+            })?;
+        let prepare_tx_hash: H256 = cell.out_point.tx_hash;
+        let prepare_header = HeaderView::from(get_any_transaction(client, prepare_tx_hash)?.header);
+        let occupied_capacity = output
+            .occupied_capacity(Capacity::bytes(data.len())?)?
+            .as_u64();
+        input_capacity += calculate_dao_maximum_withdraw4(
+            deposit_header,
+            &prepare_header,
+            &output,
+            occupied_capacity,
+        );
+    }
+    let output_capacity = tx
+        .outputs()
+        .into_iter()
+        .map(|cell_output| Unpack::<u64>::unpack(&cell_output.capacity()))
+        .sum::<u64>();
+    input_capacity.checked_sub(output_capacity).ok_or_else(|| {
+        anyhow!(
+            "the outputs capacity ({}) exceeds the inputs capacity ({})",
+            output_capacity,
+            input_capacity
+        )
+    })
+}
+
+/// Load the transaction (or the transfer bundle), the input cells are provided
+/// by the bundle or resolved by the light client.
+fn load_tx(
+    rpc_url: &str,
+    path: &Path,
+    resolve: bool,
+) -> Result<(TransactionView, Box<dyn TransactionDependencyProvider>), Error> {
+    let content = fs::read_to_string(path)?;
+    let value: serde_json::Value = serde_json::from_str(&content)?;
+    if value.get("input_cells").is_some() && !resolve {
+        let bundle: TransferBundle = serde_json::from_value(value)
+            .map_err(|err| anyhow!("parse transfer bundle file error: {}", err))?;
+        let bundle_provider = bundle.check()?;
+        let tx = packed::Transaction::from(bundle.transaction).into_view();
+        return Ok((tx, Box::new(bundle_provider)));
+    }
+    if !resolve {
+        return Err(anyhow!(
+            "the input cells are required, use `--resolve` to resolve them by the light client"
+        ));
+    }
+    let tx: json_types::Transaction = match value.get("transaction") {
+        Some(tx) => serde_json::from_value(tx.clone())?,
+        None => serde_json::from_value(value)?,
+    };
+    Ok((
+        packed::Transaction::from(tx).into_view(),
+        Box::new(LightClientTransactionDependencyProvider::new(rpc_url)),
+    ))
+}
+
+/// Load the transaction (or the transfer bundle) and generate the lock script
+/// groups, the group index is the position in the returned list.
+fn load_tx_lock_groups(
+    rpc_url: &str,
+    path: &Path,
+    resolve: bool,
+) -> Result<(TransactionView, Vec<ScriptGroup>), Error> {
+    let (tx, tx_dep_provider) = load_tx(rpc_url, path, resolve)?;
+    let lock_groups = TransferBundle::gen_script_groups(&tx, tx_dep_provider.as_ref())?
+        .into_iter()
+        .filter(|group| group.group_type == BundleGroupType::Lock)
+        .map(ScriptGroup::from)
+        .collect();
+    Ok((tx, lock_groups))
+}
+
+fn set_witness_field(witness_args: WitnessArgs, field: WitnessField, value: Bytes) -> WitnessArgs {
+    let builder = witness_args.as_builder();
+    match field {
+        WitnessField::Lock => builder.lock(Some(value).pack()),
+        WitnessField::InputType => builder.input_type(Some(value).pack()),
+        WitnessField::OutputType => builder.output_type(Some(value).pack()),
+    }
+    .build()
+}
+
+/// Same as `generate_message` but the zeroed field of the first witness in
+/// the group can be other than the lock.
+fn generate_field_message(
+    tx: &TransactionView,
+    group: &ScriptGroup,
+    field: WitnessField,
+    zero_value: Bytes,
+) -> Result<Bytes, Error> {
+    if field == WitnessField::Lock {
+        return Ok(generate_message(tx, group, zero_value)?);
+    }
+    let witnesses: Vec<packed::Bytes> = tx.witnesses().into_iter().collect();
+    let witness_data = witnesses
+        .get(group.input_indices[0])
+        .map(|witness| witness.raw_data())
+        .ok_or_else(|| anyhow!("witness not enough"))?;
+    let witness_args = if witness_data.is_empty() {
+        WitnessArgs::default()
+    } else {
+        WitnessArgs::from_slice(witness_data.as_ref())
+            .map_err(|err| anyhow!("invalid witness args: {}", err))?
+    };
+    let init_witness = set_witness_field(witness_args, field, zero_value).as_bytes();
+    let mut blake2b = new_blake2b();
+    blake2b.update(tx.hash().as_slice());
+    blake2b.update(&(init_witness.len() as u64).to_le_bytes());
+    blake2b.update(&init_witness);
+    // The other witnesses in the group, then the witnesses not covered by any inputs
+    let rest_witnesses = group
+        .input_indices
+        .iter()
+        .skip(1)
+        .filter_map(|idx| witnesses.get(*idx))
+        .chain(witnesses.iter().skip(tx.inputs().len()));
+    for witness in rest_witnesses {
+        let data = witness.raw_data();
+        blake2b.update(&(data.len() as u64).to_le_bytes());
+        blake2b.update(&data);
+    }
+    let mut message = [0u8; 32];
+    blake2b.finalize(&mut message);
+    Ok(Bytes::from(message.to_vec()))
+}
+
+fn get_witness_field(
+    tx: &TransactionView,
+    group: &ScriptGroup,
+    field: WitnessField,
+) -> Result<Bytes, Error> {
+    let witness_data = tx
+        .witnesses()
+        .get(group.input_indices[0])
+        .map(|witness| witness.raw_data())
+        .unwrap_or_default();
+    if witness_data.is_empty() {
+        return Ok(Bytes::default());
+    }
+    let witness_args = WitnessArgs::from_slice(witness_data.as_ref())
+        .map_err(|err| anyhow!("invalid witness args: {}", err))?;
+    let value = match field {
+        WitnessField::Lock => witness_args.lock(),
+        WitnessField::InputType => witness_args.input_type(),
+        WitnessField::OutputType => witness_args.output_type(),
+    };
+    Ok(value.to_opt().map(|v| v.raw_data()).unwrap_or_default())
+}
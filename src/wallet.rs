@@ -1,36 +1,526 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
 
 use anyhow::{anyhow, Error};
 use ckb_hash::blake2b_256;
 use ckb_jsonrpc_types as json_types;
 use ckb_sdk::{
-    constants::{MULTISIG_TYPE_HASH, SIGHASH_TYPE_HASH},
+    constants::{DAO_TYPE_HASH, MULTISIG_TYPE_HASH, SIGHASH_TYPE_HASH},
     rpc::{
-        ckb_light_client::{CellsCapacity, ScriptType, SearchKey},
+        ckb_light_client::{CellsCapacity, Order, ScriptType, SearchKey},
         LightClientRpcClient,
     },
     traits::{
+        CellCollector, CellCollectorError, CellDepResolver, CellQueryOptions,
         DefaultCellDepResolver, LightClientCellCollector, LightClientHeaderDepResolver,
-        LightClientTransactionDependencyProvider, SecpCkbRawKeySigner, Signer,
+        LightClientTransactionDependencyProvider, LiveCell, OffchainCellDepResolver,
+        OffchainTransactionDependencyProvider, SecpCkbRawKeySigner, Signer, SignerError,
+        TransactionDependencyProvider, ValueRangeOption,
     },
-    tx_builder::{transfer::CapacityTransferBuilder, CapacityBalancer, TxBuilder},
-    unlock::{ScriptUnlocker, SecpSighashUnlocker},
+    tx_builder::{
+        gen_script_groups, transfer::CapacityTransferBuilder, unlock_tx, CapacityBalancer,
+        ScriptGroups, SinceSource, TxBuilder,
+    },
+    types::{ScriptGroup, ScriptGroupType},
+    unlock::{MultisigConfig, ScriptUnlocker, SecpMultisigUnlocker, SecpSighashUnlocker},
     Address, HumanCapacity, ScriptId, SECP256K1,
 };
 use ckb_signer::{FileSystemKeystoreSigner, KeyStore, ScryptType};
+use clap::{ArgGroup, Args, ValueEnum};
 use rpassword::prompt_password;
+use serde::{Deserialize, Serialize};
 
 use ckb_types::{
     bytes::Bytes,
-    core::{ScriptHashType, TransactionView},
-    packed::{CellOutput, Script, WitnessArgs},
+    core::{BlockView, Capacity, FeeRate, ScriptHashType, TransactionView},
+    packed::{self, CellDep, CellOutput, Script, WitnessArgs},
     prelude::*,
     H160, H256,
 };
 
-pub fn get_capacity(rpc_url: &str, address: Address) -> Result<(), Error> {
+use crate::common::{confirm, parse_capacity, HexH256};
+use crate::dao::{dao_summary, DaoSummary};
+use crate::output::Output;
+use crate::poll::{wait_tx_committed, POLL_INTERVAL};
+use crate::rpc::get_network_type;
+use crate::scripts::ScriptName;
+use crate::util::{sighash_messages, tx_fee, CkbCliTx, WitnessField};
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("from").required(true).args(["from_address", "from_key"])))]
+#[command(group(ArgGroup::new("receiver").required(true).args(["to_address", "to_lock_file", "amounts_file"])))]
+pub struct TransferArgs {
+    /// The sender address (sighash/multisig, also be used to match key in ckb-cli keystore)
+    #[arg(long, value_name = "ADDR")]
+    from_address: Option<Address>,
+
+    /// The sender private key (hex string, also be used to generate sighash address)
+    #[arg(long, value_name = "PRIVKEY")]
+    from_key: Option<HexH256>,
+
+    /// The multisig config file (required when <from-address> is a multisig address)
+    #[arg(long, value_name = "FILE", requires = "from_address")]
+    multisig_config: Option<PathBuf>,
+
+    /// The receiver address
+    #[arg(long, value_name = "ADDR", requires = "capacity")]
+    to_address: Option<Address>,
+
+    /// The receiver lock script file (json), for the lock script can't be
+    /// represented as an address
+    #[arg(long, value_name = "FILE", requires = "capacity")]
+    to_lock_file: Option<PathBuf>,
+
+    /// The capacity to transfer (unit: CKB, example: 102.43)
+    #[arg(long, value_name = "CAPACITY", value_parser = parse_capacity)]
+    capacity: Option<HumanCapacity>,
+
+    /// Transfer to multiple receivers, the file is a JSON object maps receiver
+    /// address to capacity (unit: CKB), example: {"ckt1qyq...": "102.43"}
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["to_address", "capacity"])]
+    amounts_file: Option<PathBuf>,
+
+    /// Skip the invalid receivers in the amounts file (they are reported) and
+    /// transfer to the others
+    #[arg(long, requires = "amounts_file")]
+    skip_invalid: bool,
+
+    /// The memo attached to the receiver output (UTF-8 encoded as the output data)
+    #[arg(long, value_name = "STRING", conflicts_with = "amounts_file")]
+    memo: Option<String>,
+
+    /// Skip check <to-address> (default only allow sighash/multisig address), be cautious to use this flag
+    #[arg(long)]
+    skip_check_to_address: bool,
+
+    /// The lock scripts <to-address> is allowed to be (the well-known lock
+    /// scripts of the network)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "NAME",
+        default_values = ["sighash", "multisig"],
+        conflicts_with = "skip_check_to_address"
+    )]
+    allowed_to_lock: Vec<ScriptName>,
+
+    /// Export the unsigned transaction as a transfer bundle file (sign it by
+    /// `import-bundle` later) instead of signing and sending it
+    #[arg(long, value_name = "FILE")]
+    export_bundle: Option<PathBuf>,
+
+    /// After the transaction is sent, wait for it committed then fetch it back and
+    /// check there is an output with the expected lock and capacity for each receiver
+    #[arg(long, conflicts_with = "export_bundle")]
+    verify_recipient: bool,
+
+    /// Fail instead of creating a change cell, the leftover capacity goes to
+    /// the fee (at most <max-implicit-fee>)
+    #[arg(long)]
+    no_change: bool,
+
+    /// The max fee the leftover capacity can be burned as when `--no-change`
+    /// is given (unit: CKB), the fee rate is still applied when omitted
+    #[arg(long, value_name = "CAPACITY", requires = "no_change", value_parser = parse_capacity)]
+    max_implicit_fee: Option<HumanCapacity>,
+
+    /// The change below <min-change> is added to the fee (after confirmed)
+    /// instead of creating a change cell (unit: CKB, the change cell is at least
+    /// 61 CKB anyway)
+    #[arg(long, value_name = "CAPACITY", conflicts_with = "no_change", value_parser = parse_capacity)]
+    min_change: Option<HumanCapacity>,
+
+    /// Only use the cells in blocks up to <N> (inclusive) as inputs
+    #[arg(long, value_name = "N")]
+    inputs_before_block: Option<u64>,
+
+    /// Split the change to the addresses by the weights instead of returning it
+    /// to the sender, example: ckt1qyq...:3 (the extra fee of the split is paid
+    /// from the change, each split change cell must meet its occupied capacity)
+    #[arg(long, value_name = "ADDR:WEIGHT", num_args = 1.., conflicts_with = "no_change", value_parser = parse_change_split)]
+    change_split: Vec<ChangeSplit>,
+
+    #[command(flatten)]
+    build_args: BuildArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct MonitorArgs {
+    /// Alert when the capacity of the address drops below the threshold (unit: CKB),
+    /// example: ckt1qyq...:1000.5
+    #[arg(long, value_name = "ADDR:CKB", required = true, value_parser = parse_alert_threshold)]
+    alert_below: Vec<AlertThreshold>,
+
+    /// Run the command (by `sh -c`) on alert instead of exiting with error, the
+    /// environment variables ALERT_ADDRESS, ALERT_CAPACITY and ALERT_THRESHOLD
+    /// (unit: shannon) are set for the command
+    #[arg(long, value_name = "CMD")]
+    on_alert: Option<String>,
+
+    /// The interval between two checks (unit: second)
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+}
+
+impl TransferArgs {
+    /// The addresses given in the command line and the receivers of the amounts
+    /// file (the invalid entries are reported by `transfer`)
+    pub fn addresses(&self) -> Vec<Address> {
+        let mut addresses: Vec<Address> = self
+            .from_address
+            .iter()
+            .chain(&self.to_address)
+            .chain(self.change_split.iter().map(|split| &split.address))
+            .cloned()
+            .collect();
+        if let Some(Ok(entries)) = self.amounts_file.as_deref().map(load_amounts_file) {
+            addresses.extend(
+                entries
+                    .into_iter()
+                    .filter_map(|(_, result)| result.ok().map(|(address, _)| address)),
+            );
+        }
+        addresses
+    }
+}
+
+impl MonitorArgs {
+    pub fn addresses(&self) -> Vec<Address> {
+        self.alert_below
+            .iter()
+            .map(|threshold| threshold.address.clone())
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AlertThreshold {
+    address: Address,
+    capacity: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChangeSplit {
+    address: Address,
+    weight: u64,
+}
+
+fn parse_change_split(input: &str) -> Result<ChangeSplit, String> {
+    let (address, weight) = input
+        .split_once(':')
+        .ok_or_else(|| format!("invalid change split: {}, format: ADDR:WEIGHT", input))?;
+    let address = Address::from_str(address)?;
+    let weight = weight
+        .parse::<u64>()
+        .map_err(|err| format!("invalid weight {}: {}", weight, err))?;
+    if weight == 0 {
+        return Err(format!("the weight of {} is zero", address));
+    }
+    Ok(ChangeSplit { address, weight })
+}
+
+fn parse_alert_threshold(input: &str) -> Result<AlertThreshold, String> {
+    let (address, capacity) = input
+        .split_once(':')
+        .ok_or_else(|| format!("invalid alert threshold: {}, format: ADDR:CKB", input))?;
+    let address = Address::from_str(address)?;
+    let capacity = parse_capacity(capacity)?.0;
+    Ok(AlertThreshold { address, capacity })
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("from").required(true).args(["from_address", "from_key"])))]
+pub struct ImportBundleArgs {
+    /// The transfer bundle file (generated by `transfer --export-bundle`)
+    #[arg(long, value_name = "FILE")]
+    bundle: PathBuf,
+
+    /// The sender address (sighash/multisig, also be used to match key in ckb-cli keystore)
+    #[arg(long, value_name = "ADDR")]
+    from_address: Option<Address>,
+
+    /// The sender private key (hex string, also be used to generate sighash address)
+    #[arg(long, value_name = "PRIVKEY")]
+    from_key: Option<HexH256>,
+
+    /// The multisig config file (required when <from-address> is a multisig address)
+    #[arg(long, value_name = "FILE", requires = "from_address")]
+    multisig_config: Option<PathBuf>,
+
+    /// Save the signed bundle to the file instead of sending the transaction
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// The default fee rate (shannons/KB) to build the transactions
+pub const DEFAULT_FEE_RATE: u64 = 1000;
+
+/// Options shared by all the transaction building commands
+#[derive(Args, Debug)]
+pub struct BuildArgs {
+    /// Load the system scripts cell deps from the file (generated by `util dump-cell-deps`)
+    /// instead of the genesis block
+    #[arg(long, value_name = "FILE")]
+    cell_deps_file: Option<PathBuf>,
+
+    /// Print the resolved input cells of the built transaction
+    #[arg(long)]
+    show_inputs: bool,
+
+    /// When the transaction is rejected by the min fee rate of the tx pool,
+    /// rebuild it with a higher fee rate and resend, at most <N> times
+    #[arg(long, value_name = "N", default_value = "0")]
+    auto_bump_retries: u32,
+
+    /// Dump the genesis block, cell deps, input cells, header deps and the built
+    /// transaction into the directory (for reproducing the build offline)
+    #[arg(long, value_name = "DIR")]
+    dump_fixture: Option<PathBuf>,
+
+    /// After the transaction is sent, wait for it committed then fetch it back and
+    /// check it is identical to the built transaction
+    #[arg(long)]
+    verify_after_send: bool,
+
+    /// Build the transaction but don't send it, print the fee, the resolved
+    /// inputs, the outputs, the cell deps, the header deps and the witnesses
+    #[arg(long, conflicts_with = "verify_after_send")]
+    dry_run_verbose: bool,
+
+    /// Export the transaction to the file in ckb-cli `tx` format (the signatures
+    /// are included) instead of sending it, so that it can be signed and sent by
+    /// `ckb-cli tx` subcommands
+    #[arg(long, value_name = "FILE", conflicts_with = "verify_after_send")]
+    export_ckb_cli_tx: Option<PathBuf>,
+
+    /// Print the sighash-all message of each sighash/multisig lock group (the
+    /// same as `util tx-sighash` computes), for comparing with other signers
+    #[arg(long)]
+    dump_sighash: bool,
+}
+
+/// The details of the built transaction printed by `--dry-run-verbose`
+#[derive(Serialize)]
+struct DryRunTrace {
+    tx_hash: H256,
+    fee_rate: u64,
+    size: usize,
+    min_fee: json_types::Capacity,
+    fee: json_types::Capacity,
+    inputs: Vec<BundleInputCell>,
+    outputs: Vec<DryRunOutput>,
+    cell_deps: Vec<json_types::CellDep>,
+    header_deps: Vec<H256>,
+    witnesses: Vec<DryRunWitness>,
+}
+
+#[derive(Serialize)]
+struct DryRunOutput {
+    capacity: json_types::Capacity,
+    lock_hash: H256,
+    type_hash: Option<H256>,
+    data_len: usize,
+}
+
+#[derive(Serialize)]
+struct DryRunWitness {
+    size: usize,
+    /// The size of `WitnessArgs.lock` (none if the witness is not a `WitnessArgs`)
+    lock_size: Option<usize>,
+}
+
+impl BuildArgs {
+    /// Export the transaction in ckb-cli `tx` format if `--export-ckb-cli-tx` is given
+    pub fn export_ckb_cli_tx(
+        &self,
+        client: &mut LightClientRpcClient,
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<(), Error> {
+        if let Some(path) = self.export_ckb_cli_tx.as_ref() {
+            let network = get_network_type(client)?;
+            let ckb_cli_tx = CkbCliTx::new(tx, tx_dep_provider, network)?;
+            fs::write(path, serde_json::to_string_pretty(&ckb_cli_tx).unwrap())?;
+            println!(
+                "transaction exported to: {} (ckb-cli tx format)",
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Print the details of the built transaction if `--dry-run-verbose` is given
+    pub fn trace(
+        &self,
+        client: &mut LightClientRpcClient,
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+        fee_rate: u64,
+        output: &Output,
+    ) -> Result<(), Error> {
+        if !self.dry_run_verbose {
+            return Ok(());
+        }
+        let inputs = resolve_input_cells(tx, tx_dep_provider)?;
+        let outputs = tx
+            .outputs()
+            .into_iter()
+            .zip(tx.outputs_data())
+            .map(|(output, data)| DryRunOutput {
+                capacity: Unpack::<u64>::unpack(&output.capacity()).into(),
+                lock_hash: output.lock().calc_script_hash().unpack(),
+                type_hash: output
+                    .type_()
+                    .to_opt()
+                    .map(|script| script.calc_script_hash().unpack()),
+                data_len: data.raw_data().len(),
+            })
+            .collect::<Vec<_>>();
+        let size = tx.data().as_reader().serialized_size_in_block();
+        let witnesses = tx
+            .witnesses()
+            .into_iter()
+            .map(|witness| {
+                let witness = witness.raw_data();
+                DryRunWitness {
+                    size: witness.len(),
+                    lock_size: WitnessArgs::from_slice(&witness)
+                        .ok()
+                        .map(|args| args.lock().to_opt().map_or(0, |lock| lock.raw_data().len())),
+                }
+            })
+            .collect();
+        let trace = DryRunTrace {
+            tx_hash: tx.hash().unpack(),
+            fee_rate,
+            size,
+            min_fee: FeeRate::from_u64(fee_rate).fee(size).as_u64().into(),
+            fee: tx_fee(client, tx, tx_dep_provider)?.into(),
+            inputs,
+            outputs,
+            cell_deps: tx.cell_deps().into_iter().map(Into::into).collect(),
+            header_deps: tx.header_deps_iter().map(|hash| hash.unpack()).collect(),
+            witnesses,
+        };
+        output.print_json(&trace)
+    }
+
+    /// `--dry-run-verbose` is given, the transaction is built but not signed or sent
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run_verbose
+    }
+
+    /// Get the sender and the signer by `get_signer`, the signer is empty (by
+    /// `get_unsigned_signer`) for the dry run, so no password is asked.
+    pub fn get_signer(
+        &self,
+        from_address: Option<Address>,
+        from_key: Option<H256>,
+        multisig_config: Option<&Path>,
+    ) -> Result<SenderInfo, Error> {
+        if self.dry_run_verbose {
+            get_unsigned_signer(from_address, from_key, multisig_config)
+        } else {
+            get_signer(from_address, from_key, multisig_config)
+        }
+    }
+
+    /// Print the signing messages of the lock groups if `--dump-sighash` is given
+    pub fn dump_sighash(
+        &self,
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<(), Error> {
+        if self.dump_sighash {
+            let lock_groups: Vec<_> = TransferBundle::gen_script_groups(tx, tx_dep_provider)?
+                .into_iter()
+                .filter(|group| group.group_type == BundleGroupType::Lock)
+                .map(ScriptGroup::from)
+                .collect();
+            let messages = sighash_messages(tx, &lock_groups, WitnessField::Lock)?;
+            println!(
+                "sighash: {}",
+                serde_json::to_string_pretty(&messages).unwrap()
+            );
+        }
+        Ok(())
+    }
+
+    /// Print the input cells if `--show-inputs` is given
+    pub fn show_inputs(
+        &self,
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<(), Error> {
+        if self.show_inputs {
+            let input_cells = resolve_input_cells(tx, tx_dep_provider)?;
+            println!(
+                "inputs: {}",
+                serde_json::to_string_pretty(&input_cells).unwrap()
+            );
+        }
+        Ok(())
+    }
+
+    /// Dump the fixture files if `--dump-fixture` is given
+    pub fn dump_fixture(
+        &self,
+        client: &mut LightClientRpcClient,
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<(), Error> {
+        let dir = if let Some(dir) = self.dump_fixture.as_ref() {
+            dir
+        } else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir)?;
+        let write_json = |name: &str, content: String| fs::write(dir.join(name), content);
+
+        let genesis_block = client.get_genesis_block()?;
+        let cell_deps = CellDepsFile::from_genesis(&genesis_block.clone().into())?;
+        write_json(
+            "genesis_block.json",
+            serde_json::to_string_pretty(&genesis_block).unwrap(),
+        )?;
+        write_json(
+            "cell_deps.json",
+            serde_json::to_string_pretty(&cell_deps).unwrap(),
+        )?;
+        let input_cells = resolve_input_cells(tx, tx_dep_provider)?;
+        write_json(
+            "input_cells.json",
+            serde_json::to_string_pretty(&input_cells).unwrap(),
+        )?;
+        let mut headers = Vec::new();
+        for block_hash in tx.header_deps_iter() {
+            let block_hash: H256 = block_hash.unpack();
+            let header = client
+                .get_header(block_hash.clone())?
+                .ok_or_else(|| anyhow!("header dep not found: {:#x}", block_hash))?;
+            headers.push(header);
+        }
+        write_json(
+            "header_deps.json",
+            serde_json::to_string_pretty(&headers).unwrap(),
+        )?;
+        write_json(
+            "transaction.json",
+            serde_json::to_string_pretty(&json_types::TransactionView::from(tx.clone())).unwrap(),
+        )?;
+        println!("fixture dumped to: {}", dir.display());
+        Ok(())
+    }
+}
+
+pub fn get_capacity(rpc_url: &str, address: Address, output: &Output) -> Result<(), Error> {
     let mut client = LightClientRpcClient::new(rpc_url);
     let script = Script::from(&address).into();
     let (synced_number, cells_capacity) = check_address(&mut client, script)?;
@@ -38,29 +528,272 @@ pub fn get_capacity(rpc_url: &str, address: Address) -> Result<(), Error> {
     println!("tip number: {}", cells_capacity.block_number.value());
     println!("tip hash: {:#x}", cells_capacity.block_hash);
     println!(
-        "capacity: {} CKB",
-        HumanCapacity(cells_capacity.capacity.value())
+        "capacity: {}",
+        output.format_capacity_with_unit(cells_capacity.capacity.value())
     );
     Ok(())
 }
 
-pub fn transfer(
-    rpc_url: &str,
-    from_address: Option<Address>,
-    from_key: Option<H256>,
-    to_address: Address,
-    capacity: u64,
-    skip_check_to_address: bool,
-    debug: bool,
-) -> Result<(), Error> {
-    let tx = build_transfer_tx(
-        rpc_url,
-        from_address,
-        from_key,
-        to_address,
-        capacity,
-        skip_check_to_address,
+/// The overview of an address: the capacity, the NervosDAO position and the
+/// sUDT balances.
+#[derive(Serialize)]
+struct Portfolio {
+    address: String,
+    tip_number: json_types::BlockNumber,
+    /// The capacity of all the live cells (include the DAO cells)
+    capacity: json_types::Capacity,
+    /// The capacity not locked in NervosDAO
+    free_capacity: json_types::Capacity,
+    dao: DaoSummary,
+    /// free capacity + DAO principal + DAO reward
+    total_capacity: json_types::Capacity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_usd: Option<String>,
+    sudt: Vec<SudtBalance>,
+}
+
+/// The sUDT balance of a token (identified by the type script hash)
+#[derive(Serialize)]
+struct SudtBalance {
+    type_hash: H256,
+    /// The lock hash of the token issuer (the type script args)
+    owner_lock_hash: json_types::JsonBytes,
+    cells: usize,
+    capacity: json_types::Capacity,
+    /// The amount is u128, so it's a decimal string
+    balance: String,
+}
+
+pub fn portfolio(rpc_url: &str, address: Address, output: &Output) -> Result<(), Error> {
+    let mut client = LightClientRpcClient::new(rpc_url);
+    let (_, cells_capacity) = check_address(&mut client, Script::from(&address).into())?;
+    let capacity = cells_capacity.capacity.value();
+    let dao = dao_summary(rpc_url, &address)?;
+    let free_capacity = capacity.saturating_sub(dao.principal());
+    let total_capacity = free_capacity + dao.principal() + dao.reward.value();
+    let sudt = sudt_balances(rpc_url, &address)?;
+    output.print_json(&Portfolio {
+        address: address.to_string(),
+        tip_number: cells_capacity.block_number,
+        capacity: capacity.into(),
+        free_capacity: free_capacity.into(),
+        dao,
+        total_capacity: total_capacity.into(),
+        total_usd: output.usd_value(total_capacity),
+        sudt,
+    })
+}
+
+/// The first and the last transaction of an address
+#[derive(Serialize)]
+struct AddressActivity {
+    address: String,
+    first_seen: TxSeen,
+    last_seen: TxSeen,
+}
+
+#[derive(Serialize)]
+struct TxSeen {
+    tx_hash: H256,
+    block_number: json_types::BlockNumber,
+    timestamp: json_types::Timestamp,
+}
+
+pub fn address_activity(rpc_url: &str, address: Address, output: &Output) -> Result<(), Error> {
+    let mut client = LightClientRpcClient::new(rpc_url);
+    let script: json_types::Script = Script::from(&address).into();
+    let (synced_number, _) = check_address(&mut client, script.clone())?;
+    println!("synchronized number: {}", synced_number);
+    let search_key = SearchKey {
+        script,
+        script_type: ScriptType::Lock,
+        filter: None,
+        with_data: Some(false),
+        group_by_transaction: Some(true),
+    };
+    let mut find_tx = |order: Order| -> Result<Option<TxSeen>, Error> {
+        let page = client.get_transactions(search_key.clone(), order, 1.into(), None)?;
+        let tx = match page.objects.into_iter().next() {
+            Some(tx) => serde_json::to_value(tx)?,
+            None => return Ok(None),
+        };
+        // The fields of `Tx` are private
+        let tx_hash: H256 = serde_json::from_value(tx["transaction"]["hash"].clone())?;
+        let header = client
+            .get_transaction(tx_hash.clone())?
+            .ok_or_else(|| anyhow!("transaction not found: {:#x}", tx_hash))?
+            .header;
+        Ok(Some(TxSeen {
+            tx_hash,
+            block_number: header.inner.number,
+            timestamp: header.inner.timestamp,
+        }))
+    };
+    let first_seen = match find_tx(Order::Asc)? {
+        Some(tx) => tx,
+        None => {
+            return Err(anyhow!(
+                "no transaction of the address found (synchronized to block {})",
+                synced_number
+            ))
+        }
+    };
+    let last_seen = find_tx(Order::Desc)?.expect("last transaction");
+    output.print_json(&AddressActivity {
+        address: address.to_string(),
+        first_seen,
+        last_seen,
+    })
+}
+
+/// Sum the sUDT amounts (the first 16 bytes of the cell data) of the live
+/// cells of the address, grouped by the type script.
+fn sudt_balances(rpc_url: &str, address: &Address) -> Result<Vec<SudtBalance>, Error> {
+    let (code_hash, hash_type) = match ScriptName::Sudt.code_hash(address.network()) {
+        Some(code_hash) => code_hash,
+        None => {
+            eprintln!(
+                "WARNING: sUDT script is unknown in {:?} network, skip the sUDT balances",
+                address.network()
+            );
+            return Ok(Vec::new());
+        }
+    };
+    // Empty args as the prefix to match all the sUDT tokens
+    let sudt_type_script = Script::new_builder()
+        .code_hash(code_hash.pack())
+        .hash_type(hash_type.into())
+        .build();
+    let mut query = CellQueryOptions::new_lock(Script::from(address));
+    query.secondary_script = Some(sudt_type_script);
+    query.min_total_capacity = u64::MAX;
+    let mut cell_collector = LightClientCellCollector::new(rpc_url);
+    let (cells, _) = cell_collector.collect_live_cells(&query, false)?;
+
+    // type hash => (type script args, cells, capacity, amount)
+    let mut balances: BTreeMap<H256, (Bytes, usize, u64, u128)> = BTreeMap::new();
+    for cell in cells {
+        let type_script = match cell.output.type_().to_opt() {
+            Some(type_script) if cell.output_data.len() >= 16 => type_script,
+            _ => continue,
+        };
+        let capacity: u64 = cell.output.capacity().unpack();
+        let amount = u128::from_le_bytes(cell.output_data[0..16].try_into().unwrap());
+        let type_hash: H256 = type_script.calc_script_hash().unpack();
+        let balance = balances
+            .entry(type_hash.clone())
+            .or_insert_with(|| (type_script.args().raw_data(), 0, 0, 0));
+        balance.1 += 1;
+        balance.2 += capacity;
+        balance.3 = balance
+            .3
+            .checked_add(amount)
+            .ok_or_else(|| anyhow!("sUDT balance overflow: {:#x}", type_hash))?;
+    }
+    Ok(balances
+        .into_iter()
+        .map(|(type_hash, (args, cells, capacity, amount))| SudtBalance {
+            type_hash,
+            owner_lock_hash: json_types::JsonBytes::from_bytes(args),
+            cells,
+            capacity: capacity.into(),
+            balance: amount.to_string(),
+        })
+        .collect())
+}
+
+/// Check the capacity of the addresses periodically, alert when the capacity
+/// drops below the threshold. Without `--on-alert` the first alert ends the
+/// monitor with error, otherwise the command runs once each time the capacity
+/// drops below the threshold.
+pub fn monitor(rpc_url: &str, args: MonitorArgs, output: &Output) -> Result<(), Error> {
+    let mut client = LightClientRpcClient::new(rpc_url);
+    let mut alerted = vec![false; args.alert_below.len()];
+    loop {
+        for (threshold, alerted) in args.alert_below.iter().zip(alerted.iter_mut()) {
+            let script = Script::from(&threshold.address).into();
+            let (_, cells_capacity) = check_address(&mut client, script)?;
+            let capacity = cells_capacity.capacity.value();
+            println!(
+                "[tip: {}] {}: {}",
+                cells_capacity.block_number.value(),
+                threshold.address,
+                output.format_capacity_with_unit(capacity)
+            );
+            if capacity >= threshold.capacity {
+                *alerted = false;
+                continue;
+            }
+            if *alerted {
+                continue;
+            }
+            *alerted = true;
+            let message = format!(
+                "ALERT: the capacity of {} is below {}",
+                threshold.address,
+                output.format_capacity_with_unit(threshold.capacity)
+            );
+            let cmd = match args.on_alert.as_ref() {
+                Some(cmd) => cmd,
+                None => return Err(anyhow!(message)),
+            };
+            eprintln!("{}", message);
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(cmd)
+                .env("ALERT_ADDRESS", threshold.address.to_string())
+                .env("ALERT_CAPACITY", capacity.to_string())
+                .env("ALERT_THRESHOLD", threshold.capacity.to_string())
+                .status()
+                .map_err(|err| anyhow!("run alert command error: {}", err))?;
+            if !status.success() {
+                eprintln!("WARNING: the alert command exited with {}", status);
+            }
+        }
+        thread::sleep(Duration::from_secs(args.interval));
+    }
+}
+
+/// Sign the transaction in the transfer bundle offline, then send it or save
+/// the signed bundle.
+pub fn import_bundle(rpc_url: &str, args: ImportBundleArgs, debug: bool) -> Result<(), Error> {
+    let content = fs::read_to_string(&args.bundle)?;
+    let bundle: TransferBundle = serde_json::from_str(&content)
+        .map_err(|err| anyhow!("parse transfer bundle file error: {}", err))?;
+    let tx_dep_provider = bundle.check()?;
+
+    let (_sender, signer, multisig_config) = get_signer(
+        args.from_address,
+        args.from_key.map(|v| v.0),
+        args.multisig_config.as_deref(),
     )?;
+    let unlockers = build_unlockers(signer, multisig_config);
+    let tx = packed::Transaction::from(bundle.transaction.clone()).into_view();
+    let (tx, still_locked_groups) = unlock_tx(tx, &tx_dep_provider, &unlockers)?;
+
+    if let Some(path) = args.output {
+        let bundle = TransferBundle {
+            transaction: tx.data().into(),
+            ..bundle
+        };
+        fs::write(&path, serde_json::to_string_pretty(&bundle).unwrap())?;
+        println!(
+            "signed bundle saved to: {} (still locked groups: {})",
+            path.display(),
+            still_locked_groups.len()
+        );
+        return Ok(());
+    }
+    if !still_locked_groups.is_empty() {
+        return Err(anyhow!(
+            "the transaction is not fully signed ({} script groups still locked), use `--output` to save the signed bundle",
+            still_locked_groups.len()
+        ));
+    }
+    send_tx(rpc_url, tx, debug)
+}
+
+fn send_tx(rpc_url: &str, tx: TransactionView, debug: bool) -> Result<(), Error> {
     // Send transaction
     let json_tx = json_types::TransactionView::from(tx);
     if debug {
@@ -73,93 +806,962 @@ pub fn transfer(
     Ok(())
 }
 
-fn build_transfer_tx(
+/// Build the transaction with the fee rate (shannons/KB) and send it. When the
+/// transaction is rejected by the min fee rate of the tx pool, rebuild it with
+/// a higher fee rate (based on the min fee rate reported by the node) and
+/// retry, at most `--auto-bump-retries` times.
+///
+/// Returns None when `--dry-run-verbose` or `--export-ckb-cli-tx` is given (the
+/// transaction is not sent).
+pub fn send_with_fee_bump<F>(
     rpc_url: &str,
-    from_address: Option<Address>,
-    from_key: Option<H256>,
-    to_address: Address,
-    capacity: u64,
-    skip_check_to_address: bool,
-) -> Result<TransactionView, Error> {
-    let (sender, signer) = get_signer(from_address, from_key)?;
+    build_args: &BuildArgs,
+    debug: bool,
+    mut build_tx: F,
+) -> Result<Option<H256>, Error>
+where
+    F: FnMut(u64) -> Result<TransactionView, Error>,
+{
+    let mut client = LightClientRpcClient::new(rpc_url);
+    let mut fee_rate = DEFAULT_FEE_RATE;
+    let mut retry = 0;
+    loop {
+        let tx = build_tx(fee_rate)?;
+        if build_args.dry_run_verbose {
+            println!("dry run, the transaction is not sent");
+            return Ok(None);
+        }
+        if build_args.export_ckb_cli_tx.is_some() {
+            return Ok(None);
+        }
+        let json_tx = json_types::TransactionView::from(tx.clone());
+        if debug {
+            println!("tx: {}", serde_json::to_string_pretty(&json_tx).unwrap());
+        }
+        match client.send_transaction(json_tx.inner) {
+            Ok(tx_hash) => {
+                println!(">>> tx sent! {:#x} <<<", tx_hash);
+                if build_args.verify_after_send {
+                    verify_sent_tx(&mut client, &tx, &tx_hash)?;
+                }
+                return Ok(Some(tx_hash));
+            }
+            Err(err) => {
+                let err_msg = err.to_string();
+                if !err_msg.contains("PoolRejectedTransactionByMinFeeRate")
+                    || retry >= build_args.auto_bump_retries
+                {
+                    return Err(anyhow!("send transaction error: {}", err_msg));
+                }
+                let min_fee_rate = parse_min_fee_rate(&err_msg).unwrap_or(fee_rate);
+                let new_fee_rate = min_fee_rate.max(fee_rate) * 3 / 2;
+                retry += 1;
+                println!(
+                    "rejected by min fee rate (fee rate: {} shannons/KB), retry({}) with fee rate: {} shannons/KB",
+                    fee_rate, retry, new_fee_rate
+                );
+                fee_rate = new_fee_rate;
+            }
+        }
+    }
+}
+
+/// The cell collector only collects the cells in blocks up to <max_block_number>
+struct BlockRangeCellCollector {
+    inner: LightClientCellCollector,
+    max_block_number: Option<u64>,
+}
+
+impl BlockRangeCellCollector {
+    fn new(rpc_url: &str, max_block_number: Option<u64>) -> BlockRangeCellCollector {
+        BlockRangeCellCollector {
+            inner: LightClientCellCollector::new(rpc_url),
+            max_block_number,
+        }
+    }
+}
+
+impl CellCollector for BlockRangeCellCollector {
+    fn collect_live_cells(
+        &mut self,
+        query: &CellQueryOptions,
+        apply_changes: bool,
+    ) -> Result<(Vec<LiveCell>, u64), CellCollectorError> {
+        match self.max_block_number {
+            Some(number) => {
+                let mut query = query.clone();
+                query.block_range = Some(ValueRangeOption::new(0, number.saturating_add(1)));
+                self.inner.collect_live_cells(&query, apply_changes)
+            }
+            None => self.inner.collect_live_cells(query, apply_changes),
+        }
+    }
+
+    fn lock_cell(&mut self, out_point: packed::OutPoint) -> Result<(), CellCollectorError> {
+        self.inner.lock_cell(out_point)
+    }
+
+    fn apply_tx(&mut self, tx: packed::Transaction) -> Result<(), CellCollectorError> {
+        self.inner.apply_tx(tx)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+}
+
+/// A field differs between the built and the committed transaction
+#[derive(Serialize)]
+struct FieldDiff {
+    /// The JSON path of the field, e.g. "outputs[1].capacity"
+    path: String,
+    built: Option<serde_json::Value>,
+    committed: Option<serde_json::Value>,
+}
+
+/// Compare the JSON values field by field, collect the different leaf fields
+/// (or the missing array items / object fields)
+fn diff_json(
+    path: &str,
+    built: &serde_json::Value,
+    committed: &serde_json::Value,
+    diffs: &mut Vec<FieldDiff>,
+) {
+    use serde_json::Value;
+    match (built, committed) {
+        (Value::Object(built), Value::Object(committed)) => {
+            let field_path = |key: &str| {
+                if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                }
+            };
+            for (key, value) in built {
+                let field_path = field_path(key);
+                match committed.get(key) {
+                    Some(other) => diff_json(&field_path, value, other, diffs),
+                    None => diffs.push(FieldDiff {
+                        path: field_path,
+                        built: Some(value.clone()),
+                        committed: None,
+                    }),
+                }
+            }
+            for (key, value) in committed {
+                if !built.contains_key(key) {
+                    diffs.push(FieldDiff {
+                        path: field_path(key),
+                        built: None,
+                        committed: Some(value.clone()),
+                    });
+                }
+            }
+        }
+        (Value::Array(built), Value::Array(committed)) => {
+            for index in 0..built.len().max(committed.len()) {
+                let item_path = format!("{}[{}]", path, index);
+                match (built.get(index), committed.get(index)) {
+                    (Some(value), Some(other)) => diff_json(&item_path, value, other, diffs),
+                    (value, other) => diffs.push(FieldDiff {
+                        path: item_path,
+                        built: value.cloned(),
+                        committed: other.cloned(),
+                    }),
+                }
+            }
+        }
+        _ if built != committed => diffs.push(FieldDiff {
+            path: path.to_string(),
+            built: Some(built.clone()),
+            committed: Some(committed.clone()),
+        }),
+        _ => {}
+    }
+}
+
+/// Fetch the sent transaction back (after it's committed) and compare it with
+/// the built one.
+fn verify_sent_tx(
+    client: &mut LightClientRpcClient,
+    tx: &TransactionView,
+    tx_hash: &H256,
+) -> Result<(), Error> {
+    let built_hash: H256 = tx.hash().unpack();
+    if &built_hash != tx_hash {
+        return Err(anyhow!(
+            "the returned transaction hash {:#x} is not the built transaction hash {:#x}",
+            tx_hash,
+            built_hash
+        ));
+    }
+    let tx_with_header = wait_tx_committed(client, tx_hash, POLL_INTERVAL)?;
+    let fetched_tx = packed::Transaction::from(tx_with_header.transaction.inner).into_view();
+    let mut mismatches = Vec::new();
+    if fetched_tx.hash() != tx.hash() {
+        mismatches.push("hash");
+    }
+    if fetched_tx.inputs().as_slice() != tx.inputs().as_slice() {
+        mismatches.push("inputs");
+    }
+    if fetched_tx.outputs().as_slice() != tx.outputs().as_slice() {
+        mismatches.push("outputs");
+    }
+    if fetched_tx.outputs_data().as_slice() != tx.outputs_data().as_slice() {
+        mismatches.push("outputs data");
+    }
+    if fetched_tx.witnesses().as_slice() != tx.witnesses().as_slice() {
+        mismatches.push("witnesses");
+    }
+    if !mismatches.is_empty() {
+        let to_value = |tx: &TransactionView| {
+            serde_json::to_value(json_types::Transaction::from(tx.data())).unwrap()
+        };
+        let mut diffs = Vec::new();
+        diff_json("", &to_value(tx), &to_value(&fetched_tx), &mut diffs);
+        println!("diff: {}", serde_json::to_string_pretty(&diffs).unwrap());
+        return Err(anyhow!(
+            "the committed transaction {:#x} is different from the built one: {}",
+            tx_hash,
+            mismatches.join(", ")
+        ));
+    }
+    println!(
+        "transaction verified (committed in block {})",
+        tx_with_header.header.inner.number.value()
+    );
+    Ok(())
+}
+
+/// Parse the min fee rate from the error message:
+///   "The min fee rate is 1000 shannons/KW, ..."
+fn parse_min_fee_rate(err_msg: &str) -> Option<u64> {
+    let (_, rest) = err_msg.split_once("min fee rate is ")?;
+    rest.split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|value| value.parse::<u64>().ok())
+}
+
+pub fn transfer(
+    rpc_url: &str,
+    args: TransferArgs,
+    output: &Output,
+    debug: bool,
+) -> Result<(), Error> {
+    let TransferArgs {
+        from_address,
+        from_key,
+        multisig_config,
+        to_address,
+        to_lock_file,
+        capacity,
+        amounts_file,
+        skip_invalid,
+        memo,
+        skip_check_to_address,
+        allowed_to_lock,
+        export_bundle,
+        verify_recipient,
+        no_change,
+        max_implicit_fee,
+        min_change,
+        inputs_before_block,
+        change_split,
+        build_args,
+    } = args;
+    if let Some(name) = allowed_to_lock.iter().find(|name| !name.is_lock()) {
+        let name = name.to_possible_value().expect("value");
+        return Err(anyhow!("{} is not a lock script", name.get_name()));
+    }
+    let check_to_address = |address: &Address| {
+        if skip_check_to_address {
+            Ok(())
+        } else {
+            check_to_address(address, &allowed_to_lock)
+        }
+    };
+    for split in &change_split {
+        check_to_address(&split.address)?;
+    }
+    change_split
+        .iter()
+        .try_fold(0u64, |total, split| total.checked_add(split.weight))
+        .ok_or_else(|| anyhow!("the total weight of the change split overflows"))?;
+    let data = memo.map(Bytes::from).unwrap_or_default();
+    // The receivers: (the display name, lock script, capacity)
+    let candidates = if let Some(path) = to_lock_file.as_ref() {
+        let content = fs::read_to_string(path)?;
+        let lock: json_types::Script = serde_json::from_str(&content)
+            .map_err(|err| anyhow!("parse lock script file error: {}", err))?;
+        let lock = Script::from(lock);
+        let name = format!("lock script {:#x}", lock.calc_script_hash());
+        vec![(name, Ok((lock, capacity.expect("capacity").0)))]
+    } else if let Some(path) = amounts_file.as_ref() {
+        load_amounts_file(path)?
+            .into_iter()
+            .map(|(name, result)| {
+                let result = result.and_then(|(address, capacity)| {
+                    check_to_address(&address)?;
+                    Ok((Script::from(&address), capacity))
+                });
+                (name, result)
+            })
+            .collect()
+    } else {
+        let address = to_address.expect("to address");
+        check_to_address(&address)?;
+        let lock = Script::from(&address);
+        vec![(
+            address.to_string(),
+            Ok((lock, capacity.expect("capacity").0)),
+        )]
+    };
+    let mut receivers = Vec::new();
+    let mut outputs = Vec::new();
+    let mut invalid_receivers = Vec::new();
+    for (name, result) in candidates {
+        let result = result.and_then(|(lock, capacity)| {
+            let output = CellOutput::new_builder()
+                .lock(lock.clone())
+                .capacity(capacity.pack())
+                .build();
+            let occupied_capacity = output
+                .occupied_capacity(Capacity::bytes(data.len())?)?
+                .as_u64();
+            if capacity < occupied_capacity {
+                return Err(anyhow!(
+                    "the capacity to {} is less than the minimal capacity: {} CKB",
+                    name,
+                    HumanCapacity(occupied_capacity)
+                ));
+            }
+            Ok((lock, capacity, output))
+        });
+        match result {
+            Ok((lock, capacity, output)) => {
+                receivers.push((name, lock, capacity));
+                outputs.push((output, data.clone()));
+            }
+            Err(err) => invalid_receivers.push((name, err)),
+        }
+    }
+    if !invalid_receivers.is_empty() {
+        if amounts_file.is_none() {
+            return Err(invalid_receivers.remove(0).1);
+        }
+        println!("invalid receivers:");
+        for (name, err) in &invalid_receivers {
+            println!("  {}: {}", name, err);
+        }
+        if !skip_invalid {
+            return Err(anyhow!(
+                "{} invalid receivers in the amounts file, use `--skip-invalid` to transfer to the others",
+                invalid_receivers.len()
+            ));
+        }
+        if receivers.is_empty() {
+            return Err(anyhow!("no valid receiver in the amounts file"));
+        }
+        println!("skipped {} invalid receivers", invalid_receivers.len());
+    }
+    let total = receivers
+        .iter()
+        .try_fold(0u64, |total, (_, _, capacity)| total.checked_add(*capacity))
+        .ok_or_else(|| anyhow!("the total capacity overflow"))?;
+    if amounts_file.is_some() {
+        println!("receivers:");
+        for (name, _, capacity) in &receivers {
+            println!(
+                "  {}: {}",
+                name,
+                output.format_capacity_with_unit(*capacity)
+            );
+        }
+        println!("total: {}", output.format_capacity_with_unit(total));
+        if !confirm("Confirm the transfer?")? {
+            return Err(anyhow!("transfer canceled"));
+        }
+    }
+    // The bundle is signed later, so use an empty signer to build the
+    // unlockers (only used to fill the placeholder witnesses).
+    let (sender, signer, multisig_config) = if export_bundle.is_some() {
+        get_unsigned_signer(
+            from_address,
+            from_key.map(|v| v.0),
+            multisig_config.as_deref(),
+        )?
+    } else {
+        build_args.get_signer(
+            from_address,
+            from_key.map(|v| v.0),
+            multisig_config.as_deref(),
+        )?
+    };
     let mut client = LightClientRpcClient::new(rpc_url);
     let (synced_number, cells_capacity) = check_address(&mut client, sender.clone().into())?;
     println!("synchronized number: {}", synced_number);
     println!("tip number: {}", cells_capacity.block_number.value());
     println!("tip hash: {:#x}", cells_capacity.block_hash);
+    let available = cells_capacity.capacity.value();
+    if total > available {
+        return Err(anyhow!(
+            "insufficient capacity: transfer {} CKB in total, but the sender only has {} CKB (short of {} CKB)",
+            HumanCapacity(total),
+            HumanCapacity(available),
+            HumanCapacity(total - available)
+        ));
+    }
 
-    let sighash_unlocker = SecpSighashUnlocker::from(signer);
-    let sighash_script_id = ScriptId::new_type(SIGHASH_TYPE_HASH.clone());
-    let mut unlockers = HashMap::default();
-    unlockers.insert(
-        sighash_script_id,
-        Box::new(sighash_unlocker) as Box<dyn ScriptUnlocker>,
-    );
+    let unlockers = build_unlockers(signer, multisig_config);
+    let placeholder_witness = placeholder_witness(&unlockers, &sender)?;
 
     // Build:
     //   * CellDepResolver
     //   * HeaderDepResolver
     //   * CellCollector
     //   * TransactionDependencyProvider
-    let genesis_block = client.get_genesis_block()?.into();
-    let cell_dep_resolver = DefaultCellDepResolver::from_genesis(&genesis_block)?;
+    let cell_dep_resolver = get_cell_dep_resolver(&mut client, &build_args)?;
     let header_dep_resolver = LightClientHeaderDepResolver::new(rpc_url);
     let tx_dep_provider = LightClientTransactionDependencyProvider::new(rpc_url);
-    let mut cell_collector = LightClientCellCollector::new(rpc_url);
 
     // Build CapacityBalancer
-    let placeholder_witness = WitnessArgs::new_builder()
-        .lock(Some(Bytes::from(vec![0u8; 65])).pack())
-        .build();
-    let balancer = CapacityBalancer::new_simple(sender, placeholder_witness, 1000);
+    let mut balancer = CapacityBalancer::new_simple_with_since(
+        sender.clone(),
+        placeholder_witness,
+        get_since_source(&sender),
+        DEFAULT_FEE_RATE,
+    );
+    if no_change {
+        // The leftover which can't create a change cell is burned as fee
+        balancer.force_small_change_as_fee = max_implicit_fee.map(|fee| fee.0);
+    }
+    // The balancer appends the change cell after the receiver outputs
+    let output_count = outputs.len();
+    let check_no_change = |tx: &TransactionView| {
+        if no_change && tx.outputs().len() > output_count {
+            let change: u64 = tx.output(output_count).expect("change").capacity().unpack();
+            return Err(anyhow!(
+                "the transaction would create a change cell of {} CKB (`--no-change` is given)",
+                HumanCapacity(change)
+            ));
+        }
+        Ok(())
+    };
+    let apply_min_change = |tx: TransactionView| -> Result<TransactionView, Error> {
+        let min_change = match min_change {
+            Some(min_change) if tx.outputs().len() > output_count => min_change.0,
+            _ => return Ok(tx),
+        };
+        let change: u64 = tx.output(output_count).expect("change").capacity().unpack();
+        if change >= min_change {
+            return Ok(tx);
+        }
+        eprintln!(
+            "the change {} CKB is below the min change, it will be added to the fee",
+            HumanCapacity(change)
+        );
+        if !confirm(&format!(
+            "Burn the change {} CKB as the fee?",
+            HumanCapacity(change)
+        ))? {
+            return Err(anyhow!("transfer canceled"));
+        }
+        Ok(tx
+            .as_advanced_builder()
+            .set_outputs(tx.outputs().into_iter().take(output_count).collect())
+            .set_outputs_data(tx.outputs_data().into_iter().take(output_count).collect())
+            .build())
+    };
+    let apply_change_split = |tx: TransactionView, fee_rate: u64| {
+        if change_split.is_empty() {
+            return Ok(tx);
+        }
+        if tx.outputs().len() <= output_count {
+            eprintln!("WARNING: the transaction has no change cell, `--change-split` is ignored");
+            return Ok(tx);
+        }
+        split_change(tx, output_count, &change_split, fee_rate)
+    };
 
     // Build the transaction
-    let receiver = Script::from(&to_address);
-    let to_address_hash_type = to_address.payload().hash_type();
-    let to_address_code_hash: H256 = to_address
-        .payload()
-        .code_hash(Some(to_address.network()))
-        .unpack();
-    let to_address_args_len = to_address.payload().args().len();
-    if !(skip_check_to_address
-        || (to_address_hash_type == ScriptHashType::Type
-            && to_address_code_hash == SIGHASH_TYPE_HASH
-            && to_address_args_len == 20)
-        || (to_address_hash_type == ScriptHashType::Type
-            && to_address_code_hash == MULTISIG_TYPE_HASH
-            && (to_address_args_len == 20 || to_address_args_len == 28)))
+    let builder = CapacityTransferBuilder::new(outputs);
+    if let Some(path) = export_bundle {
+        let mut cell_collector = BlockRangeCellCollector::new(rpc_url, inputs_before_block);
+        let tx = builder.build_balanced(
+            &mut cell_collector,
+            cell_dep_resolver.as_ref(),
+            &header_dep_resolver,
+            &tx_dep_provider,
+            &balancer,
+            &unlockers,
+        )?;
+        let tx = apply_change_split(apply_min_change(tx)?, DEFAULT_FEE_RATE)?;
+        check_no_change(&tx)?;
+        build_args.show_inputs(&tx, &tx_dep_provider)?;
+        build_args.trace(&mut client, &tx, &tx_dep_provider, DEFAULT_FEE_RATE, output)?;
+        build_args.dump_sighash(&tx, &tx_dep_provider)?;
+        build_args.dump_fixture(&mut client, &tx, &tx_dep_provider)?;
+        let bundle = TransferBundle::new(&tx, &tx_dep_provider)?;
+        fs::write(&path, serde_json::to_string_pretty(&bundle).unwrap())?;
+        println!("transfer bundle exported to: {}", path.display());
+        return Ok(());
+    }
+    let tx_hash = send_with_fee_bump(rpc_url, &build_args, debug, |fee_rate| {
+        balancer.fee_rate = FeeRate::from_u64(fee_rate);
+        // The collector locks the collected cells, so use a new one for each build
+        let mut cell_collector = BlockRangeCellCollector::new(rpc_url, inputs_before_block);
+        let tx = builder.build_balanced(
+            &mut cell_collector,
+            cell_dep_resolver.as_ref(),
+            &header_dep_resolver,
+            &tx_dep_provider,
+            &balancer,
+            &unlockers,
+        )?;
+        let tx = apply_change_split(apply_min_change(tx)?, fee_rate)?;
+        check_no_change(&tx)?;
+        if build_args.is_dry_run() {
+            // Trace the unsigned transaction (with the placeholder witnesses)
+            build_args.show_inputs(&tx, &tx_dep_provider)?;
+            build_args.trace(&mut client, &tx, &tx_dep_provider, fee_rate, output)?;
+            return Ok(tx);
+        }
+        // Sign after the change is adjusted
+        let (tx, still_locked_groups) = unlock_tx(tx, &tx_dep_provider, &unlockers)?;
+        assert!(still_locked_groups.is_empty());
+        build_args.show_inputs(&tx, &tx_dep_provider)?;
+        build_args.dump_sighash(&tx, &tx_dep_provider)?;
+        build_args.export_ckb_cli_tx(&mut client, &tx, &tx_dep_provider)?;
+        build_args.dump_fixture(&mut client, &tx, &tx_dep_provider)?;
+        Ok(tx)
+    })?;
+    if let (true, Some(tx_hash)) = (verify_recipient, tx_hash) {
+        verify_recipients(&mut client, &tx_hash, &receivers, &data)?;
+    }
+    Ok(())
+}
+
+/// Fetch the sent transaction back (after it's committed) and check each
+/// receiver got an output with the expected lock script, capacity and data.
+fn verify_recipients(
+    client: &mut LightClientRpcClient,
+    tx_hash: &H256,
+    receivers: &[(String, Script, u64)],
+    data: &Bytes,
+) -> Result<(), Error> {
+    let tx_with_header = wait_tx_committed(client, tx_hash, POLL_INTERVAL)?;
+    let tx = packed::Transaction::from(tx_with_header.transaction.inner).into_view();
+    // One output can only match one receiver (the same receiver may appear twice)
+    let mut matched = vec![false; tx.outputs().len()];
+    for (name, lock, capacity) in receivers {
+        let found = tx
+            .outputs_with_data_iter()
+            .enumerate()
+            .find(|(idx, (output, output_data))| {
+                let output_capacity: u64 = output.capacity().unpack();
+                !matched[*idx]
+                    && output.lock().as_slice() == lock.as_slice()
+                    && output_capacity == *capacity
+                    && output_data == data
+            })
+            .map(|(idx, _)| idx);
+        match found {
+            Some(idx) => matched[idx] = true,
+            None => {
+                return Err(anyhow!(
+                    "no output to {} with capacity {} CKB in the committed transaction {:#x}",
+                    name,
+                    HumanCapacity(*capacity),
+                    tx_hash
+                ))
+            }
+        }
+    }
+    println!(
+        "recipient verified: {} receiver(s) (committed in block {})",
+        receivers.len(),
+        tx_with_header.header.inner.number.value()
+    );
+    Ok(())
+}
+
+/// Check the lock script of <to-address> is one of the allowed lock scripts
+/// (and the args length is valid for sighash/multisig)
+fn check_to_address(to_address: &Address, allowed: &[ScriptName]) -> Result<(), Error> {
+    let script = Script::from(to_address);
+    let args_len = to_address.payload().args().len();
+    let is_allowed = allowed.iter().any(|name| {
+        name.is_instance(to_address.network(), &script)
+            && match name {
+                ScriptName::Sighash => args_len == 20,
+                ScriptName::Multisig => args_len == 20 || args_len == 28,
+                _ => true,
+            }
+    });
+    if !is_allowed {
+        return Err(anyhow!("Invalid to-address: {}\n[Hint]: Add `--allowed-to-lock <NAME>` option to allow the lock script, or `--skip-check-to-address` flag to transfer to any address", to_address));
+    }
+    Ok(())
+}
+
+/// Replace the change cell (at <change_index>) with the change cells of the
+/// split addresses, the capacity is split by the weights after paying the fee
+/// of the larger transaction.
+fn split_change(
+    tx: TransactionView,
+    change_index: usize,
+    splits: &[ChangeSplit],
+    fee_rate: u64,
+) -> Result<TransactionView, Error> {
+    let change: u64 = tx.output(change_index).expect("change").capacity().unpack();
+    let build =
+        |capacities: &[u64]| {
+            let outputs = tx.outputs().into_iter().take(change_index).chain(
+                splits.iter().zip(capacities).map(|(split, capacity)| {
+                    CellOutput::new_builder()
+                        .lock(Script::from(&split.address))
+                        .capacity(capacity.pack())
+                        .build()
+                }),
+            );
+            let outputs_data = tx
+                .outputs_data()
+                .into_iter()
+                .take(change_index)
+                .chain(splits.iter().map(|_| Default::default()));
+            tx.as_advanced_builder()
+                .set_outputs(outputs.collect())
+                .set_outputs_data(outputs_data.collect())
+                .build()
+        };
+    let size = |tx: &TransactionView| tx.data().as_reader().serialized_size_in_block();
+    let fee_rate = FeeRate::from_u64(fee_rate);
+    let extra_fee = fee_rate
+        .fee(size(&build(&vec![0; splits.len()])))
+        .as_u64()
+        .saturating_sub(fee_rate.fee(size(&tx)).as_u64());
+    let distributable = change.checked_sub(extra_fee).ok_or_else(|| {
+        anyhow!(
+            "the change {} CKB can't pay the extra fee of splitting",
+            HumanCapacity(change)
+        )
+    })?;
+    // The total weight is checked not to overflow before building
+    let total_weight: u64 = splits.iter().map(|split| split.weight).sum();
+    let mut capacities: Vec<u64> = splits
+        .iter()
+        .map(|split| (distributable as u128 * split.weight as u128 / total_weight as u128) as u64)
+        .collect();
+    // The rounding remainder goes to the first address
+    capacities[0] += distributable - capacities.iter().sum::<u64>();
+    let split_tx = build(&capacities);
+    for (split, output) in splits
+        .iter()
+        .zip(split_tx.outputs().into_iter().skip(change_index))
     {
-        return Err(anyhow!("Invalid to-address: {}\n[Hint]: Add `--skip-check-to-address` flag to transfer to any address", to_address));
+        let capacity: u64 = output.capacity().unpack();
+        let occupied = output.occupied_capacity(Capacity::zero())?.as_u64();
+        if capacity < occupied {
+            return Err(anyhow!(
+                "the split change to {} is {} CKB, less than the occupied capacity {} CKB",
+                split.address,
+                HumanCapacity(capacity),
+                HumanCapacity(occupied)
+            ));
+        }
     }
-    let output = CellOutput::new_builder()
-        .lock(receiver)
-        .capacity(capacity.pack())
-        .build();
-    let builder = CapacityTransferBuilder::new(vec![(output, Bytes::default())]);
-    let (tx, still_locked_groups) = builder.build_unlocked(
-        &mut cell_collector,
-        &cell_dep_resolver,
-        &header_dep_resolver,
-        &tx_dep_provider,
-        &balancer,
-        &unlockers,
-    )?;
-    assert!(still_locked_groups.is_empty());
-    Ok(tx)
+    Ok(split_tx)
+}
+
+/// An entry of the amounts file: the address string and the parsed receiver
+type AmountEntry = (String, Result<(Address, u64), Error>);
+
+/// Load the receivers from the amounts file (address => capacity in CKB), each
+/// entry is validated separately so that all the invalid ones can be reported.
+fn load_amounts_file(path: &Path) -> Result<Vec<AmountEntry>, Error> {
+    let content = fs::read_to_string(path)?;
+    let amounts: BTreeMap<String, String> = serde_json::from_str(&content)
+        .map_err(|err| anyhow!("parse amounts file error: {}", err))?;
+    if amounts.is_empty() {
+        return Err(anyhow!("no receiver in the amounts file"));
+    }
+    Ok(amounts
+        .into_iter()
+        .map(|(address, capacity)| {
+            let result = Address::from_str(&address)
+                .map_err(|err| anyhow!("invalid receiver address {}: {}", address, err))
+                .and_then(|addr| {
+                    let capacity = parse_capacity(&capacity)
+                        .map_err(|err| anyhow!("invalid capacity of {}: {}", address, err))?
+                        .0;
+                    if capacity == 0 {
+                        return Err(anyhow!("the capacity to {} is zero", address));
+                    }
+                    Ok((addr, capacity))
+                });
+            (address, result)
+        })
+        .collect())
+}
+
+/// The portable transfer bundle, contains the unsigned transaction, the input
+/// cells and the script groups, so that the transaction can be signed offline.
+#[derive(Serialize, Deserialize)]
+pub struct TransferBundle {
+    pub transaction: json_types::Transaction,
+    pub input_cells: Vec<BundleInputCell>,
+    pub script_groups: Vec<BundleScriptGroup>,
+}
+
+/// Resolve the input out-points to the full cells
+pub fn resolve_input_cells(
+    tx: &TransactionView,
+    tx_dep_provider: &dyn TransactionDependencyProvider,
+) -> Result<Vec<BundleInputCell>, Error> {
+    let mut input_cells = Vec::new();
+    for input in tx.inputs() {
+        let out_point = input.previous_output();
+        let output = tx_dep_provider.get_cell(&out_point)?;
+        let output_data = tx_dep_provider.get_cell_data(&out_point)?;
+        input_cells.push(BundleInputCell {
+            out_point: out_point.into(),
+            output: output.into(),
+            output_data: json_types::JsonBytes::from_bytes(output_data),
+        });
+    }
+    Ok(input_cells)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BundleInputCell {
+    pub out_point: json_types::OutPoint,
+    pub output: json_types::CellOutput,
+    pub output_data: json_types::JsonBytes,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+pub struct BundleScriptGroup {
+    pub script: json_types::Script,
+    pub group_type: BundleGroupType,
+    pub input_indices: Vec<usize>,
+    pub output_indices: Vec<usize>,
+}
+
+impl From<BundleScriptGroup> for ScriptGroup {
+    fn from(group: BundleScriptGroup) -> ScriptGroup {
+        ScriptGroup {
+            script: group.script.into(),
+            group_type: match group.group_type {
+                BundleGroupType::Lock => ScriptGroupType::Lock,
+                BundleGroupType::Type => ScriptGroupType::Type,
+            },
+            input_indices: group.input_indices,
+            output_indices: group.output_indices,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BundleGroupType {
+    Lock,
+    Type,
+}
+
+impl TransferBundle {
+    pub fn new(
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<TransferBundle, Error> {
+        Ok(TransferBundle {
+            transaction: tx.data().into(),
+            input_cells: resolve_input_cells(tx, tx_dep_provider)?,
+            script_groups: TransferBundle::gen_script_groups(tx, tx_dep_provider)?,
+        })
+    }
+
+    /// Build the offline dependency provider from the input cells
+    pub fn tx_dep_provider(&self) -> OffchainTransactionDependencyProvider {
+        let cells = self
+            .input_cells
+            .iter()
+            .map(|cell| {
+                let key = (cell.out_point.tx_hash.clone(), cell.out_point.index.value());
+                let value = (
+                    CellOutput::from(cell.output.clone()),
+                    cell.output_data.clone().into_bytes(),
+                );
+                (key, value)
+            })
+            .collect();
+        OffchainTransactionDependencyProvider {
+            txs: HashMap::default(),
+            cells,
+            headers: HashMap::default(),
+        }
+    }
+
+    /// Check the input cells and the script groups are consistent with the
+    /// transaction, return the offline dependency provider.
+    pub fn check(&self) -> Result<OffchainTransactionDependencyProvider, Error> {
+        let tx = packed::Transaction::from(self.transaction.clone()).into_view();
+        let tx_dep_provider = self.tx_dep_provider();
+        for input in tx.inputs() {
+            let out_point = input.previous_output();
+            if tx_dep_provider.get_cell(&out_point).is_err() {
+                return Err(anyhow!(
+                    "input cell not found in bundle: {}",
+                    json_types::OutPoint::from(out_point).tx_hash
+                ));
+            }
+        }
+        if TransferBundle::gen_script_groups(&tx, &tx_dep_provider)? != self.script_groups {
+            return Err(anyhow!(
+                "the script groups in bundle not match the transaction"
+            ));
+        }
+        Ok(tx_dep_provider)
+    }
+
+    /// Generate the script groups of the transaction in a stable order (lock
+    /// groups first, then ordered by the first input/output index).
+    pub fn gen_script_groups(
+        tx: &TransactionView,
+        tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<Vec<BundleScriptGroup>, Error> {
+        let ScriptGroups {
+            lock_groups,
+            type_groups,
+        } = gen_script_groups(tx, tx_dep_provider)?;
+        let mut script_groups: Vec<_> = lock_groups
+            .into_values()
+            .chain(type_groups.into_values())
+            .map(|group| BundleScriptGroup {
+                script: group.script.into(),
+                group_type: match group.group_type {
+                    ScriptGroupType::Lock => BundleGroupType::Lock,
+                    ScriptGroupType::Type => BundleGroupType::Type,
+                },
+                input_indices: group.input_indices,
+                output_indices: group.output_indices,
+            })
+            .collect();
+        // Keep the order stable (the groups are generated from hash maps)
+        script_groups.sort_by_key(|group| {
+            (
+                group.group_type,
+                group.input_indices.first().cloned(),
+                group.output_indices.first().cloned(),
+            )
+        });
+        Ok(script_groups)
+    }
+}
+
+/// The cell dep of a system script
+#[derive(Serialize, Deserialize)]
+pub struct CellDepItem {
+    pub code_hash: H256,
+    pub hash_type: json_types::ScriptHashType,
+    pub cell_dep: json_types::CellDep,
+    pub name: String,
+}
+
+/// The cell deps file format
+#[derive(Serialize, Deserialize)]
+pub struct CellDepsFile {
+    pub genesis_hash: H256,
+    pub items: Vec<CellDepItem>,
+}
+
+impl CellDepsFile {
+    pub fn from_genesis(genesis_block: &BlockView) -> Result<CellDepsFile, Error> {
+        let resolver = DefaultCellDepResolver::from_genesis(genesis_block)?;
+        let items = [SIGHASH_TYPE_HASH, MULTISIG_TYPE_HASH, DAO_TYPE_HASH]
+            .into_iter()
+            .filter_map(|code_hash| {
+                let script_id = ScriptId::new_type(code_hash);
+                resolver
+                    .get(&script_id)
+                    .map(|(cell_dep, name)| CellDepItem {
+                        code_hash: script_id.code_hash.clone(),
+                        hash_type: script_id.hash_type.into(),
+                        cell_dep: cell_dep.clone().into(),
+                        name: name.clone(),
+                    })
+            })
+            .collect();
+        Ok(CellDepsFile {
+            genesis_hash: genesis_block.hash().unpack(),
+            items,
+        })
+    }
+}
+
+/// Load the cell deps from `--cell-deps-file` or resolve them from the genesis block
+pub fn get_cell_dep_resolver(
+    client: &mut LightClientRpcClient,
+    build_args: &BuildArgs,
+) -> Result<Box<dyn CellDepResolver>, Error> {
+    if let Some(path) = build_args.cell_deps_file.as_ref() {
+        let content = fs::read_to_string(path)?;
+        let cell_deps: CellDepsFile = serde_json::from_str(&content)
+            .map_err(|err| anyhow!("parse cell deps file error: {}", err))?;
+        let items = cell_deps
+            .items
+            .into_iter()
+            .map(|item| {
+                let script_id = ScriptId::new(item.code_hash, item.hash_type.into());
+                (script_id, (CellDep::from(item.cell_dep), item.name))
+            })
+            .collect();
+        Ok(Box::new(OffchainCellDepResolver { items }))
+    } else {
+        let genesis_block = client.get_genesis_block()?.into();
+        Ok(Box::new(DefaultCellDepResolver::from_genesis(
+            &genesis_block,
+        )?))
+    }
+}
+
+/// How many times (1 second apart) to check again for the script not found in
+/// `get_scripts` (when `--wait-script-accepted` is given)
+const SCRIPT_ACCEPT_RETRIES: u32 = 3;
+
+static WAIT_SCRIPT_ACCEPTED: AtomicBool = AtomicBool::new(false);
+
+/// Wait for the script not found in `get_scripts` to be accepted (it may be set
+/// by `rpc set-scripts` just now) instead of failing fast
+pub fn set_wait_script_accepted(wait: bool) {
+    WAIT_SCRIPT_ACCEPTED.store(wait, Ordering::SeqCst);
 }
 
 pub fn check_address(
     client: &mut LightClientRpcClient,
     script: json_types::Script,
 ) -> Result<(u64, CellsCapacity), Error> {
-    let synced_number = if let Some(status) = client
-        .get_scripts()?
-        .iter()
-        .find(|status| status.script == script)
-    {
-        status.block_number.value()
+    let max_retries = if WAIT_SCRIPT_ACCEPTED.load(Ordering::SeqCst) {
+        SCRIPT_ACCEPT_RETRIES
     } else {
-        return Err(anyhow!("address not registered, you may use `rpc set-scripts` subcommand to register the address"));
+        0
+    };
+    let mut retry = 0;
+    let synced_number = loop {
+        if let Some(status) = client
+            .get_scripts()?
+            .iter()
+            .find(|status| status.script == script)
+        {
+            break status.block_number.value();
+        }
+        if retry == max_retries {
+            return Err(anyhow!("address not registered, you may use `rpc set-scripts` subcommand to register the address (or `--wait-script-accepted` if it's registered just now)"));
+        }
+        if retry == 0 {
+            eprintln!("the address is not found in the light client scripts, waiting for it to be accepted...");
+        }
+        thread::sleep(Duration::from_secs(1));
+        retry += 1;
     };
     let search_key = SearchKey {
         script,
@@ -172,42 +1774,362 @@ pub fn check_address(
     Ok((synced_number, cells_capacity))
 }
 
+/// The sender lock script, the signer and the multisig config (if the sender is
+/// a multisig address).
+pub type SenderInfo = (Script, Box<dyn Signer>, Option<MultisigConfig>);
+
+/// Get the sender lock script and the signer, dispatched by the code hash of
+/// the sender address:
+///   * sighash address: sign by the key in ckb-cli keystore
+///   * multisig address: sign by the keys in ckb-cli keystore, the multisig
+///     config file is required
+///
+/// When `from_key` is given the sender is always the sighash address of the key.
 pub fn get_signer(
     from_address: Option<Address>,
     from_key: Option<H256>,
-) -> Result<(Script, Box<dyn Signer>), Error> {
-    let from_key = from_key
-        .map(|data| {
-            secp256k1::SecretKey::from_slice(data.as_bytes())
-                .map_err(|err| anyhow!("invalid from key: {}", err))
-        })
-        .transpose()?;
-    if let Some(privkey) = from_key {
-        let sender = {
-            let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, &privkey);
-            let hash160 = blake2b_256(&pubkey.serialize()[..])[0..20].to_vec();
-            Script::new_builder()
-                .code_hash(SIGHASH_TYPE_HASH.pack())
-                .hash_type(ScriptHashType::Type.into())
-                .args(Bytes::from(hash160).pack())
-                .build()
-        };
+    multisig_config: Option<&Path>,
+) -> Result<SenderInfo, Error> {
+    if let Some(privkey) = from_key.map(parse_from_key).transpose()? {
+        let sender = sighash_script_from_key(&privkey);
         let signer = SecpCkbRawKeySigner::new_with_secret_keys(vec![privkey]);
-        Ok((sender, Box::new(signer) as Box<_>))
+        return Ok((sender, Box::new(signer) as Box<_>, None));
+    }
+
+    let from_address = from_address.expect("from address");
+    let (sender, multisig_config) = get_address_sender(&from_address, multisig_config)?;
+    if let Some(config) = multisig_config {
+        let signer = get_multisig_signer(&config)?;
+        Ok((sender, Box::new(signer) as Box<_>, Some(config)))
     } else {
-        let from_address = from_address.expect("from address");
-        let sender = Script::from(&from_address);
-        if sender.code_hash().as_slice() != SIGHASH_TYPE_HASH.as_bytes()
-            || sender.hash_type().as_slice() != [ScriptHashType::Type as u8]
-            || sender.args().raw_data().len() != 20
-        {
-            return Err(anyhow!("from address is not sighash address"));
-        }
         let account = H160::from_slice(sender.args().raw_data().as_ref()).unwrap();
         let pass = prompt_password("Password: ")?;
         let signer = FileSystemKeystoreSigner::new(get_keystore()?);
         signer.unlock(&account, pass.as_bytes())?;
-        Ok((sender, Box::new(signer) as Box<_>))
+        Ok((sender, Box::new(signer) as Box<_>, None))
+    }
+}
+
+/// Unlock the keystore account(s) of the sender (as `get_signer` does before
+/// signing) to check the password is correct
+pub fn check_password(from_address: Address, multisig_config: Option<&Path>) -> Result<(), Error> {
+    get_signer(Some(from_address.clone()), None, multisig_config).map_err(|err| {
+        anyhow!(
+            "unlock the keystore account of {} failed: {}",
+            from_address,
+            err
+        )
+    })?;
+    println!("password OK: {}", from_address);
+    Ok(())
+}
+
+/// Same as `get_signer`, but the keystore is not touched (no password
+/// required), for building the transaction without signing it.
+pub fn get_sender(
+    from_address: Option<Address>,
+    from_key: Option<H256>,
+    multisig_config: Option<&Path>,
+) -> Result<(Script, Option<MultisigConfig>), Error> {
+    if let Some(privkey) = from_key.map(parse_from_key).transpose()? {
+        return Ok((sighash_script_from_key(&privkey), None));
+    }
+    let from_address = from_address.expect("from address");
+    get_address_sender(&from_address, multisig_config)
+}
+
+/// Same as `get_signer`, but the signer is empty (no password required), the
+/// unlockers built by it only fill the placeholder witnesses.
+pub fn get_unsigned_signer(
+    from_address: Option<Address>,
+    from_key: Option<H256>,
+    multisig_config: Option<&Path>,
+) -> Result<SenderInfo, Error> {
+    let (sender, multisig_config) = get_sender(from_address, from_key, multisig_config)?;
+    let signer = SecpCkbRawKeySigner::new_with_secret_keys(vec![]);
+    Ok((sender, Box::new(signer) as Box<_>, multisig_config))
+}
+
+fn parse_from_key(data: H256) -> Result<secp256k1::SecretKey, Error> {
+    secp256k1::SecretKey::from_slice(data.as_bytes())
+        .map_err(|err| anyhow!("invalid from key: {}", err))
+}
+
+fn sighash_script_from_key(privkey: &secp256k1::SecretKey) -> Script {
+    let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, privkey);
+    let hash160 = blake2b_256(&pubkey.serialize()[..])[0..20].to_vec();
+    Script::new_builder()
+        .code_hash(SIGHASH_TYPE_HASH.pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(hash160).pack())
+        .build()
+}
+
+/// Check the sender address (must be sighash/multisig address) and load the
+/// multisig config when the sender is a multisig address.
+fn get_address_sender(
+    from_address: &Address,
+    multisig_config: Option<&Path>,
+) -> Result<(Script, Option<MultisigConfig>), Error> {
+    let sender = Script::from(from_address);
+    let code_hash: H256 = sender.code_hash().unpack();
+    if sender.hash_type().as_slice() != [ScriptHashType::Type as u8] {
+        return Err(anyhow!(
+            "from address is neither sighash address nor multisig address"
+        ));
+    }
+    if code_hash == SIGHASH_TYPE_HASH {
+        if sender.args().raw_data().len() != 20 {
+            return Err(anyhow!("invalid sighash address args length"));
+        }
+        if multisig_config.is_some() {
+            return Err(anyhow!(
+                "from address is sighash address, `--multisig-config` is not needed"
+            ));
+        }
+        Ok((sender, None))
+    } else if code_hash == MULTISIG_TYPE_HASH {
+        let args = sender.args().raw_data();
+        if args.len() != 20 && args.len() != 28 {
+            return Err(anyhow!("invalid multisig address args length"));
+        }
+        let config_path = multisig_config.ok_or_else(|| {
+            anyhow!("from address is multisig address, `--multisig-config` is required")
+        })?;
+        let config = load_multisig_config(config_path)?;
+        if config.hash160().as_bytes() != &args[0..20] {
+            return Err(anyhow!(
+                "the multisig config not match the from address, expected lock args: {:#x}",
+                config.hash160()
+            ));
+        }
+        Ok((sender, Some(config)))
+    } else {
+        Err(anyhow!(
+            "from address is neither sighash address nor multisig address"
+        ))
+    }
+}
+
+pub type Unlockers = HashMap<ScriptId, Box<dyn ScriptUnlocker>>;
+
+/// Build the unlockers map of the sender lock script
+pub fn build_unlockers(
+    signer: Box<dyn Signer>,
+    multisig_config: Option<MultisigConfig>,
+) -> Unlockers {
+    let mut unlockers: Unlockers = HashMap::default();
+    if let Some(config) = multisig_config {
+        let multisig_unlocker = SecpMultisigUnlocker::from((signer, config));
+        unlockers.insert(
+            ScriptId::new_type(MULTISIG_TYPE_HASH.clone()),
+            Box::new(multisig_unlocker),
+        );
+    } else {
+        let sighash_unlocker = SecpSighashUnlocker::from(signer);
+        unlockers.insert(
+            ScriptId::new_type(SIGHASH_TYPE_HASH.clone()),
+            Box::new(sighash_unlocker),
+        );
+    }
+    unlockers
+}
+
+/// The placeholder witness of the sender lock script (used to estimate the
+/// transaction size), it's filled by the unlocker of the lock script so that
+/// the size always matches the final signed witness.
+pub fn placeholder_witness(unlockers: &Unlockers, sender: &Script) -> Result<WitnessArgs, Error> {
+    let unlocker = unlockers
+        .get(&ScriptId::from(sender))
+        .ok_or_else(|| anyhow!("no unlocker for the sender lock script"))?;
+    let tx = TransactionView::new_advanced_builder()
+        .input(packed::CellInput::default())
+        .build();
+    let script_group = ScriptGroup {
+        script: sender.clone(),
+        group_type: ScriptGroupType::Lock,
+        input_indices: vec![0],
+        output_indices: vec![],
+    };
+    let tx_dep_provider = OffchainTransactionDependencyProvider {
+        txs: HashMap::default(),
+        cells: HashMap::default(),
+        headers: HashMap::default(),
+    };
+    let tx = unlocker.fill_placeholder_witness(&tx, &script_group, &tx_dep_provider)?;
+    let witness = tx.witnesses().get(0).expect("placeholder witness");
+    WitnessArgs::from_slice(witness.raw_data().as_ref())
+        .map_err(|err| anyhow!("invalid placeholder witness: {}", err))
+}
+
+/// Multisig lock script with since value in args (the args length is 28)
+/// require the since field of the inputs.
+pub fn get_since_source(sender: &Script) -> SinceSource {
+    if sender.args().raw_data().len() == 28 {
+        SinceSource::LockArgs(20)
+    } else {
+        SinceSource::default()
+    }
+}
+
+// The multisig config file format (json):
+// {
+//   "sighash_addresses": ["ckt1qyq...", "ckt1qyq..."],
+//   "require_first_n": 0,
+//   "threshold": 2
+// }
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MultisigConfigFile {
+    sighash_addresses: Vec<String>,
+    require_first_n: u8,
+    threshold: u8,
+}
+
+pub fn load_multisig_config(path: &Path) -> Result<MultisigConfig, Error> {
+    let content = fs::read_to_string(path)?;
+    let config_file: MultisigConfigFile = serde_json::from_str(&content)
+        .map_err(|err| anyhow!("parse multisig config file error: {}", err))?;
+    let sighash_addresses = config_file
+        .sighash_addresses
+        .iter()
+        .map(|addr| {
+            let address = Address::from_str(addr)
+                .map_err(|err| anyhow!("invalid multisig config address {}: {}", addr, err))?;
+            let script = Script::from(&address);
+            if script.code_hash().as_slice() != SIGHASH_TYPE_HASH.as_bytes()
+                || script.hash_type().as_slice() != [ScriptHashType::Type as u8]
+                || script.args().raw_data().len() != 20
+            {
+                return Err(anyhow!(
+                    "multisig config address is not sighash address: {}",
+                    addr
+                ));
+            }
+            Ok(H160::from_slice(script.args().raw_data().as_ref()).unwrap())
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    check_multisig_config(
+        &config_file.sighash_addresses,
+        &sighash_addresses,
+        config_file.require_first_n,
+        config_file.threshold,
+    )?;
+    Ok(MultisigConfig::new_with(
+        sighash_addresses,
+        config_file.require_first_n,
+        config_file.threshold,
+    )?)
+}
+
+/// Reject the multisig config which can not produce a usable multisig lock
+fn check_multisig_config(
+    addresses: &[String],
+    sighash_addresses: &[H160],
+    require_first_n: u8,
+    threshold: u8,
+) -> Result<(), Error> {
+    if sighash_addresses.is_empty() {
+        return Err(anyhow!("multisig config: `sighash_addresses` is empty"));
+    }
+    if sighash_addresses.len() > u8::MAX as usize {
+        return Err(anyhow!(
+            "multisig config: too many sighash addresses: {}, max: {}",
+            sighash_addresses.len(),
+            u8::MAX
+        ));
+    }
+    if threshold == 0 {
+        return Err(anyhow!("multisig config: `threshold` must be in 1..=255"));
+    }
+    if threshold as usize > sighash_addresses.len() {
+        return Err(anyhow!(
+            "multisig config: `threshold` ({}) is greater than the number of sighash addresses ({})",
+            threshold,
+            sighash_addresses.len()
+        ));
+    }
+    if require_first_n > threshold {
+        return Err(anyhow!(
+            "multisig config: `require_first_n` ({}) is greater than `threshold` ({})",
+            require_first_n,
+            threshold
+        ));
+    }
+    for (idx, lock_arg) in sighash_addresses.iter().enumerate() {
+        if let Some(prev_idx) = sighash_addresses[..idx].iter().position(|v| v == lock_arg) {
+            return Err(anyhow!(
+                "multisig config: duplicated sighash address (lock args: {:#x}): {} (index {}) and {} (index {})",
+                lock_arg,
+                addresses[prev_idx],
+                prev_idx,
+                addresses[idx],
+                idx
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Unlock the keystore accounts of the multisig config, only `threshold`
+/// accounts will be used to sign the transaction.
+fn get_multisig_signer(config: &MultisigConfig) -> Result<MultisigKeystoreSigner, Error> {
+    let mut keystore = get_keystore()?;
+    let first_n = config.require_first_n() as usize;
+    let threshold = config.threshold() as usize;
+    let mut accounts = Vec::new();
+    for (idx, account) in config.sighash_addresses().iter().enumerate() {
+        if accounts.len() == threshold {
+            break;
+        }
+        if keystore.has_account(account, true) {
+            accounts.push(account.clone());
+        } else if idx < first_n {
+            return Err(anyhow!(
+                "the required account (lock args: {:#x}) is not found in keystore",
+                account
+            ));
+        }
+    }
+    if accounts.len() < threshold {
+        return Err(anyhow!(
+            "not enough accounts in keystore to sign the multisig transaction, required: {}, found: {}",
+            threshold,
+            accounts.len()
+        ));
+    }
+    let signer = FileSystemKeystoreSigner::new(keystore);
+    for account in &accounts {
+        let pass = prompt_password(format!("Password (lock args: {:#x}): ", account))?;
+        signer.unlock(account, pass.as_bytes())?;
+    }
+    Ok(MultisigKeystoreSigner { signer, accounts })
+}
+
+/// A keystore signer only sign by the selected accounts
+struct MultisigKeystoreSigner {
+    signer: FileSystemKeystoreSigner,
+    accounts: Vec<H160>,
+}
+
+impl Signer for MultisigKeystoreSigner {
+    fn match_id(&self, id: &[u8]) -> bool {
+        id.len() == 20
+            && self.accounts.iter().any(|account| account.as_bytes() == id)
+            && self.signer.match_id(id)
+    }
+
+    fn sign(
+        &self,
+        id: &[u8],
+        message: &[u8],
+        recoverable: bool,
+        tx: &TransactionView,
+    ) -> Result<Bytes, SignerError> {
+        if !self.match_id(id) {
+            return Err(SignerError::IdNotFound);
+        }
+        self.signer.sign(id, message, recoverable, tx)
     }
 }
 